@@ -11,6 +11,22 @@ pub enum XcelmateError {
     #[error("({0}) missing variant for: {1}")]
     MissingVariant(String, String),
 
+    /// A number format code that Excel would refuse to open
+    #[error("invalid number format code: {0}")]
+    InvalidNumberFormat(String),
+
+    /// A text rotation outside of 0-180 degrees that is not the vertical text value 255
+    #[error("invalid text rotation: {0}")]
+    InvalidTextRotation(u32),
+
+    /// The file is a spreadsheet format that is not supported such as a binary workbook (.xlsb)
+    #[error("unsupported workbook format: {0}")]
+    UnsupportedFormat(String),
+
+    /// A reader limit was surpassed which guards against maliciously large files
+    #[error("reader limit exceeded: {0}")]
+    LimitExceeded(String),
+
     /// The `std::io` error wrapper
     #[error(transparent)]
     StdErr(#[from] std::io::Error),
@@ -36,6 +52,23 @@ pub enum XcelmateError {
     Zip(#[from] zip::result::ZipError),
 }
 
+/// Something a reader accepted without fully understanding it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// An attribute the reader does not model, so it is dropped on save
+    UnknownAttribute {
+        part: String,
+        element: String,
+        attr: String,
+    },
+    /// A value that is kept but is outside of what the spec allows
+    InvalidValue {
+        part: String,
+        element: String,
+        value: String,
+    },
+}
+
 impl From<String> for XcelmateError {
     fn from(v: String) -> Self {
         Self::XmlEof(v)
@@ -1,11 +1,211 @@
 //! The module includes extra utility tooling to help glue logic together
 use crate::errors::XcelmateError;
-use quick_xml::{Reader, Writer};
-use std::io::{BufReader, Read, Seek, Write};
-use zip::{read::ZipFile, result::ZipError, unstable::write::FileOptionsExt, write::{FileOptionExtension, FileOptions}, ZipArchive, ZipWriter};
+use quick_xml::{
+    events::{BytesDecl, Event},
+    Reader, Writer,
+};
+use std::{
+    collections::HashMap,
+    io::{BufReader, Read, Seek, Write},
+};
+use zip::{read::ZipFile, result::ZipError, write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
 
 pub(crate) type Key = usize;
 
+/// The `XmlDeclaration` controls what precedes the root element of a written part
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct XmlDeclaration {
+    /// Start the part with a UTF-8 byte order mark
+    pub(crate) bom: bool,
+    /// Write `standalone="yes"` in the declaration
+    pub(crate) standalone: bool,
+}
+impl Default for XmlDeclaration {
+    fn default() -> Self {
+        Self {
+            bom: false,
+            standalone: true,
+        }
+    }
+}
+
+/// The `SaveOptions` controls how parts are written into the zip archive
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SaveOptions {
+    /// The compression method applied to every written part
+    pub(crate) compression: CompressionMethod,
+    /// The compression level, `None` uses the method's default
+    pub(crate) level: Option<i64>,
+    /// Indent the written xml so it is readable and diffable
+    pub(crate) pretty: bool,
+    /// The declaration written for parts without an entry in `part_declarations`
+    pub(crate) declaration: XmlDeclaration,
+    /// The declaration written for specific parts keyed by their path such as `xl/styles.xml`
+    pub(crate) part_declarations: HashMap<String, XmlDeclaration>,
+}
+impl Default for SaveOptions {
+    fn default() -> Self {
+        Self {
+            compression: CompressionMethod::Deflated,
+            level: None,
+            pretty: false,
+            declaration: XmlDeclaration::default(),
+            part_declarations: HashMap::new(),
+        }
+    }
+}
+impl SaveOptions {
+    /// Toggle indented xml output
+    pub(crate) fn pretty(mut self, pretty: bool) -> Self {
+        self.pretty = pretty;
+        self
+    }
+    /// Use `declaration` for the part at `path` instead of the default one
+    pub(crate) fn part_declaration(mut self, path: &str, declaration: XmlDeclaration) -> Self {
+        self.part_declarations.insert(path.into(), declaration);
+        self
+    }
+    /// The declaration written for the part at `path`
+    pub(crate) fn declaration_for(&self, path: &str) -> XmlDeclaration {
+        self.part_declarations
+            .get(path)
+            .copied()
+            .unwrap_or(self.declaration)
+    }
+    /// The zip file options each part is started with
+    pub(crate) fn file_options(&self) -> SimpleFileOptions {
+        SimpleFileOptions::default()
+            .compression_method(self.compression)
+            .compression_level(self.level)
+    }
+    /// The xml writer each part is written with
+    pub(crate) fn xml_writer<W: Write>(&self, inner: W) -> Writer<W> {
+        if self.pretty {
+            Writer::new_with_indent(inner, b' ', 2)
+        } else {
+            Writer::new(inner)
+        }
+    }
+    /// Start the part at `path` and write its byte order mark and declaration
+    ///
+    /// # Returns
+    /// The xml writer the rest of the part is written with
+    pub(crate) fn start_part<'a, W: Write + Seek>(
+        &self,
+        writer: &'a mut ZipWriter<W>,
+        path: &str,
+    ) -> Result<Writer<&'a mut ZipWriter<W>>, XcelmateError> {
+        writer.start_file(path, self.file_options())?;
+        let declaration = self.declaration_for(path);
+        let mut xml = self.xml_writer(writer);
+        if declaration.bom {
+            xml.write_bom()?;
+        }
+        xml.write_event(Event::Decl(BytesDecl::new(
+            "1.0",
+            Some("UTF-8"),
+            declaration.standalone.then_some("yes"),
+        )))?;
+        Ok(xml)
+    }
+}
+
+/// The `ReaderLimits` caps how much a reader will accept from untrusted files
+/// so a crafted archive can not exhaust memory
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ReaderLimits {
+    /// The maximum number of shared string references and unique shared strings the
+    /// shared strings part may declare. Cells and rows of worksheets are not covered
+    pub(crate) max_shared_strings: usize,
+    /// The maximum total bytes of text read for shared strings, checked as the text of each
+    /// string is read
+    pub(crate) max_string_bytes: usize,
+}
+impl Default for ReaderLimits {
+    fn default() -> Self {
+        Self {
+            max_shared_strings: usize::MAX,
+            max_string_bytes: usize::MAX,
+        }
+    }
+}
+
+/// Generate a fresh revision uid such as `{8F1C3A2B-6D4E-4F10-9A7B-1C2D3E4F5A6B}` for
+/// newly created elements that carry `xr:uid`/`xr9:uid`
+pub(crate) fn generate_uid() -> String {
+    use std::{
+        collections::hash_map::RandomState,
+        hash::{BuildHasher, Hasher},
+        sync::atomic::{AtomicU64, Ordering},
+        time::{SystemTime, UNIX_EPOCH},
+    };
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let random = || {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        hasher.write_u128(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default(),
+        );
+        hasher.finish()
+    };
+    let (hi, lo) = (random(), random());
+    // Version 4 (random) with the RFC 4122 variant
+    let hi = (hi & 0xFFFF_FFFF_FFFF_0FFF) | 0x0000_0000_0000_4000;
+    let lo = (lo & 0x3FFF_FFFF_FFFF_FFFF) | 0x8000_0000_0000_0000;
+    format!(
+        "{{{:08X}-{:04X}-{:04X}-{:04X}-{:012X}}}",
+        hi >> 32,
+        (hi >> 16) & 0xFFFF,
+        hi & 0xFFFF,
+        lo >> 48,
+        lo & 0xFFFF_FFFF_FFFF
+    )
+}
+
+/// Convert a date serial into a `(year, month, day)` calendar date
+///
+/// The 1900 date system keeps Excel's fictitious 1900-02-29 (serial 60) so serials
+/// line up with what Excel displays, while the 1904 date system counts from 1904-01-01
+pub(crate) fn serial_to_date(serial: f64, date1904: bool) -> (i64, u32, u32) {
+    let serial = serial.trunc() as i64;
+    let days = match (date1904, serial) {
+        (true, _) => days_from_civil(1904, 1, 1) + serial,
+        (false, 60) => return (1900, 2, 29),
+        (false, ..=59) => days_from_civil(1899, 12, 31) + serial,
+        (false, _) => days_from_civil(1899, 12, 30) + serial,
+    };
+    civil_from_days(days)
+}
+
+/// Days since 1970-01-01 of a proleptic gregorian date
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Proleptic gregorian date of the days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
 // ported from calamine https://github.com/tafia/calamine/tree/master
 pub(crate) fn xml_reader<'a, RS: Read + Seek>(
     zip: &'a mut ZipArchive<RS>,
@@ -35,7 +235,69 @@ pub(crate) trait XmlWriter<W: Write> {
     fn write_xml<'a>(&self, writer: &'a mut Writer<W>, tag_name: &'a str) -> Result<&'a mut Writer<W>, XcelmateError>;
 }
 
-pub(crate) trait Save<W: Write + Seek, EX: FileOptionExtension>: XmlWriter<W> {
+pub(crate) trait Save<W: Write + Seek>: XmlWriter<W> {
     /// Save file in a zip folder aka .xlsx
-    fn save(&mut self, writer: &mut ZipWriter<W>, options: FileOptions<EX>) -> Result<(), XcelmateError>;
+    fn save(&mut self, writer: &mut ZipWriter<W>, options: &SaveOptions) -> Result<(), XcelmateError>;
+}
+
+/// Assert two xml documents are semantically equal, ignoring attribute order, insignificant
+/// whitespace, the declaration and whether an element without children is self-closing
+#[cfg(test)]
+pub(crate) fn assert_xml_eq(produced: &[u8], expected: &[u8]) {
+    use quick_xml::events::{BytesStart, Event};
+
+    fn start(e: &BytesStart) -> String {
+        let mut attrs: Vec<String> = e
+            .attributes()
+            .map(|a| {
+                let a = a.unwrap();
+                format!(
+                    "{}={:?}",
+                    String::from_utf8_lossy(a.key.as_ref()),
+                    a.unescape_value().unwrap()
+                )
+            })
+            .collect();
+        attrs.sort();
+        format!(
+            "<{} {}>",
+            String::from_utf8_lossy(e.name().as_ref()),
+            attrs.join(" ")
+        )
+    }
+
+    fn normalize(xml: &[u8]) -> Vec<String> {
+        let mut reader = Reader::from_reader(xml);
+        let mut tokens = Vec::new();
+        loop {
+            match reader.read_event().unwrap() {
+                Event::Start(e) => tokens.push(start(&e)),
+                Event::Empty(e) => {
+                    tokens.push(start(&e));
+                    tokens.push(format!("</{}>", String::from_utf8_lossy(e.name().as_ref())));
+                }
+                Event::End(e) => {
+                    tokens.push(format!("</{}>", String::from_utf8_lossy(e.name().as_ref())))
+                }
+                Event::Text(t) => {
+                    let text = t.unescape().unwrap();
+                    // Indentation between elements is insignificant but padding inside a
+                    // value is not
+                    if !text.trim().is_empty() {
+                        tokens.push(text.into_owned());
+                    }
+                }
+                Event::CData(t) => tokens.push(String::from_utf8_lossy(&t).into_owned()),
+                Event::Eof => return tokens,
+                _ => (),
+            }
+        }
+    }
+
+    let produced = normalize(produced);
+    let expected = normalize(expected);
+    for (i, (p, e)) in produced.iter().zip(expected.iter()).enumerate() {
+        assert_eq!(p, e, "xml differs at token {i}");
+    }
+    assert_eq!(produced.len(), expected.len(), "xml differs in length");
 }
@@ -1,17 +1,27 @@
 //! The module holds all logic to fully deserialize a .xlsx file and its contents
 mod shared_string_table;
 mod stylesheet;
+mod theme;
 
 use crate::errors::XcelmateError;
+use quick_xml::{events::Event, name::QName};
 use shared_string_table::SharedStringTable;
 use std::{
     fs::File,
-    io::{Read, Seek},
+    io::{Read, Seek, Write},
 };
 use stylesheet::Stylesheet;
-use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+use theme::Theme;
+use zip::{ZipArchive, ZipWriter};
 
-use super::utils::Save;
+use super::utils::{serial_to_date, xml_reader, ReaderLimits, Save, SaveOptions};
+
+/// The path of the macro project in a macro-enabled workbook (.xlsm)
+const VBA_PROJECT_PATH: &str = "xl/vbaProject.bin";
+/// The workbook part of a binary workbook (.xlsb)
+const XLSB_WORKBOOK_PATH: &str = "xl/workbook.bin";
+/// The parts that are regenerated on save, all other parts are copied verbatim
+const MANAGED_PARTS: [&str; 2] = ["xl/sharedStrings.xml", "xl/styles.xml"];
 
 /// The `Xlsx` struct represents an Excel workbook stored in an OpenXML format (XLSX).
 /// It encapsulates foundational pieces of a workbook
@@ -22,20 +32,330 @@ struct Xlsx<RS> {
     shared_string_table: SharedStringTable,
     /// The stylesheet for formating cells.
     style: Stylesheet,
+    /// The color scheme that theme colors resolve against
+    theme: Theme,
+    /// Whether dates count from 1904-01-01 instead of 1900-01-01 (common in Mac-origin files)
+    date1904: bool,
 }
 
 impl<RS: Read + Seek> Xlsx<RS> {
+    /// Open a workbook and read all supported parts
+    fn new(reader: RS) -> Result<Self, XcelmateError> {
+        Xlsx::with_limits(reader, ReaderLimits::default())
+    }
+    /// Open a workbook from an untrusted source and read all supported parts within `limits`
+    fn with_limits(reader: RS, limits: ReaderLimits) -> Result<Self, XcelmateError> {
+        let zip = ZipArchive::new(reader)?;
+        if zip
+            .file_names()
+            .any(|n| n.eq_ignore_ascii_case(XLSB_WORKBOOK_PATH))
+        {
+            return Err(XcelmateError::UnsupportedFormat("xlsb".into()));
+        }
+        let mut xlsx = Xlsx {
+            zip,
+            shared_string_table: SharedStringTable::default(),
+            style: Stylesheet::default(),
+            theme: Theme::default(),
+            date1904: false,
+        };
+        xlsx.shared_string_table.set_limits(limits);
+        xlsx.read_workbook_properties()?;
+        xlsx.read_shared_strings()?;
+        xlsx.read_stylesheet()?;
+        xlsx.theme.read_theme(&mut xlsx.zip)?;
+        Ok(xlsx)
+    }
+    /// Read the workbook wide properties of `<workbookPr>`
+    fn read_workbook_properties(&mut self) -> Result<(), XcelmateError> {
+        let mut xml = match xml_reader(&mut self.zip, "xl/workbook.xml") {
+            None => return Ok(()),
+            Some(x) => x?,
+        };
+        let mut buf = Vec::with_capacity(1024);
+        loop {
+            buf.clear();
+            match xml.read_event_into(&mut buf) {
+                Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e))
+                    if e.local_name().as_ref() == b"workbookPr" =>
+                {
+                    for attr in e.attributes() {
+                        if let Ok(a) = attr {
+                            if a.key == QName(b"date1904") {
+                                let val = a.unescape_value()?;
+                                self.date1904 = val == "1" || val == "true";
+                            }
+                        }
+                    }
+                }
+                Ok(Event::Eof) => return Ok(()),
+                Err(e) => return Err(XcelmateError::Xml(e)),
+                _ => (),
+            }
+        }
+    }
+    /// Convert a date serial into a `(year, month, day)` using the workbook's date system
+    fn serial_to_date(&self, serial: f64) -> (i64, u32, u32) {
+        serial_to_date(serial, self.date1904)
+    }
     fn read_shared_strings(&mut self) -> Result<(), XcelmateError> {
         self.shared_string_table.read_shared_strings(&mut self.zip)
     }
     fn read_stylesheet(&mut self) -> Result<(), XcelmateError> {
         self.style.read_stylesheet(&mut self.zip)
     }
-    fn save(&mut self, name: &str) -> Result<(), XcelmateError> {
-        let mut zip = ZipWriter::new(File::create(name)?);
-        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
-        let _ = self.shared_string_table.save(&mut zip, options);
-        let _ = self.style.save(&mut zip, options);
+    /// Whether the workbook carries a VBA project (macro-enabled .xlsm)
+    fn has_vba(&self) -> bool {
+        self.zip
+            .file_names()
+            .any(|n| n.eq_ignore_ascii_case(VBA_PROJECT_PATH))
+    }
+    fn save(&mut self, name: &str, options: &SaveOptions) -> Result<(), XcelmateError> {
+        self.save_to(File::create(name)?, options)?;
         Ok(())
     }
+    /// Save the workbook into any seekable writer such as an in-memory buffer
+    ///
+    /// # Returns
+    /// The writer once the archive is finished
+    fn save_to<W: Write + Seek>(
+        &mut self,
+        writer: W,
+        options: &SaveOptions,
+    ) -> Result<W, XcelmateError> {
+        let mut zip = ZipWriter::new(writer);
+
+        // Parts we do not model (worksheets, vba project, content types, etc) are
+        // copied as-is so nothing is lost on round trip
+        for i in 0..self.zip.len() {
+            let file = self.zip.by_index_raw(i)?;
+            if MANAGED_PARTS
+                .iter()
+                .any(|p| file.name().eq_ignore_ascii_case(p))
+            {
+                continue;
+            }
+            zip.raw_copy_file(file)?;
+        }
+
+        self.shared_string_table.save(&mut zip, options)?;
+        self.style.save(&mut zip, options)?;
+        Ok(zip.finish()?)
+    }
+}
+
+#[cfg(test)]
+mod xlsx_unittests {
+    use super::Xlsx;
+    use std::{
+        fs::File,
+        io::{self, Cursor, Seek, SeekFrom, Write},
+    };
+    use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+    /// An in-memory writer that fails once the stylesheet content is written
+    struct FailingStylesWriter(Cursor<Vec<u8>>);
+    impl Write for FailingStylesWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if buf.windows(b"styleSheet".len()).any(|w| w == b"styleSheet") {
+                return Err(io::Error::other("disk full"));
+            }
+            self.0.write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.flush()
+        }
+    }
+    impl Seek for FailingStylesWriter {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    /// Copy all parts of an existing workbook and add or replace any extra parts in it
+    fn rezip(path: &str, extra: &[(&str, &[u8])]) -> Cursor<Vec<u8>> {
+        let mut src = ZipArchive::new(File::open(path).unwrap()).unwrap();
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for i in 0..src.len() {
+            let file = src.by_index_raw(i).unwrap();
+            if extra.iter().any(|(name, _)| file.name() == *name) {
+                continue;
+            }
+            zip.raw_copy_file(file).unwrap();
+        }
+        for (name, bytes) in extra {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(bytes).unwrap();
+        }
+        Cursor::new(zip.finish().unwrap().into_inner())
+    }
+
+    mod xlsx_api {
+        use super::{rezip, FailingStylesWriter, Xlsx};
+        use crate::{errors::XcelmateError, stream::utils::SaveOptions};
+        use std::{
+            fs::File,
+            io::{Cursor, Read, Seek},
+        };
+        use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+        #[test]
+        fn has_no_vba() {
+            let xlsx = Xlsx::new(File::open("tests/workbook01.xlsx").unwrap()).unwrap();
+            assert!(!xlsx.has_vba());
+        }
+
+        #[test]
+        fn vba_project_is_preserved_on_save() {
+            let vba: Vec<u8> = (0..=255).cycle().take(4096).collect();
+            let mut xlsx = Xlsx::new(rezip(
+                "tests/workbook01.xlsx",
+                &[("xl/vbaProject.bin", &vba)],
+            ))
+            .unwrap();
+            assert!(xlsx.has_vba());
+
+            let buf = xlsx
+                .save_to(Cursor::new(Vec::new()), &SaveOptions::default())
+                .unwrap();
+
+            // Macro code must survive byte-for-byte
+            let mut zip = ZipArchive::new(buf).unwrap();
+            let mut actual = Vec::new();
+            zip.by_name("xl/vbaProject.bin")
+                .unwrap()
+                .read_to_end(&mut actual)
+                .unwrap();
+            assert_eq!(actual, vba);
+            assert!(zip.by_name("[Content_Types].xml").is_ok());
+            assert!(zip.by_name("xl/styles.xml").is_ok());
+        }
+
+        #[test]
+        fn stored_is_larger_than_deflated() {
+            let mut xlsx = Xlsx::new(File::open("tests/workbook04.xlsx").unwrap()).unwrap();
+            let stored = xlsx
+                .save_to(
+                    Cursor::new(Vec::new()),
+                    &SaveOptions {
+                        compression: CompressionMethod::Stored,
+                        level: None,
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+            let deflated = xlsx
+                .save_to(
+                    Cursor::new(Vec::new()),
+                    &SaveOptions {
+                        compression: CompressionMethod::Deflated,
+                        level: Some(9),
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+
+            assert!(stored.into_inner().len() > deflated.into_inner().len());
+        }
+
+        #[test]
+        fn save_to_memory() {
+            let mut xlsx = Xlsx::new(File::open("tests/workbook04.xlsx").unwrap()).unwrap();
+            let buf = xlsx
+                .save_to(Cursor::new(Vec::new()), &SaveOptions::default())
+                .unwrap();
+
+            // Every part must be present in the reopened workbook
+            let reopened = Xlsx::new(Cursor::new(buf.into_inner())).unwrap();
+            let mut expected: Vec<_> = xlsx.zip.file_names().collect();
+            let mut actual: Vec<_> = reopened.zip.file_names().collect();
+            expected.sort();
+            actual.sort();
+            assert_eq!(actual, expected);
+        }
+
+        #[test]
+        fn save_to_preserves_revision_uids() {
+            /// Every `xr:uid` and `xr9:uid` attribute with its quoted value as raw bytes
+            fn uids(styles: &[u8]) -> Vec<&[u8]> {
+                styles
+                    .split(|b| *b == b' ')
+                    .filter(|attr| attr.starts_with(b"xr:uid=") || attr.starts_with(b"xr9:uid="))
+                    .map(|attr| {
+                        let end = attr.iter().rposition(|b| *b == b'"').unwrap();
+                        &attr[..=end]
+                    })
+                    .collect()
+            }
+            fn styles<R: Read + Seek>(mut zip: ZipArchive<R>) -> Vec<u8> {
+                let mut buf = Vec::new();
+                zip.by_name("xl/styles.xml")
+                    .unwrap()
+                    .read_to_end(&mut buf)
+                    .unwrap();
+                buf
+            }
+
+            let mut xlsx = Xlsx::new(File::open("tests/workbook04.xlsx").unwrap()).unwrap();
+            let buf = xlsx
+                .save_to(Cursor::new(Vec::new()), &SaveOptions::default())
+                .unwrap();
+            let expected =
+                styles(ZipArchive::new(File::open("tests/workbook04.xlsx").unwrap()).unwrap());
+            let actual = styles(ZipArchive::new(buf).unwrap());
+
+            // Both the cellStyle and the tableStyle uids are written back byte for byte
+            let expected = uids(&expected);
+            assert!(expected.contains(&&b"xr:uid=\"{7550990F-9FC6-42E3-BF99-B26341B945F7}\""[..]));
+            assert!(expected.contains(&&b"xr9:uid=\"{00000000-0011-0000-FFFF-FFFF00000000}\""[..]));
+            assert_eq!(uids(&actual), expected);
+        }
+
+        #[test]
+        fn save_to_fails_when_a_part_fails() {
+            let mut xlsx = Xlsx::new(File::open("tests/workbook04.xlsx").unwrap()).unwrap();
+            // Stored so the part content reaches the writer as is
+            let actual = xlsx.save_to(
+                FailingStylesWriter(Cursor::new(Vec::new())),
+                &SaveOptions {
+                    compression: CompressionMethod::Stored,
+                    level: None,
+                    ..Default::default()
+                },
+            );
+            assert!(actual.is_err());
+        }
+
+        #[test]
+        fn date1904_shifts_serials() {
+            let xlsx = Xlsx::new(File::open("tests/workbook01.xlsx").unwrap()).unwrap();
+            assert!(!xlsx.date1904);
+            assert_eq!(xlsx.serial_to_date(1.0), (1900, 1, 1));
+            assert_eq!(xlsx.serial_to_date(60.0), (1900, 2, 29));
+            assert_eq!(xlsx.serial_to_date(61.0), (1900, 3, 1));
+            assert_eq!(xlsx.serial_to_date(43831.5), (2020, 1, 1));
+
+            let workbook = br#"<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><workbookPr date1904="1"/><sheets><sheet name="Sheet1" sheetId="1"/></sheets></workbook>"#;
+            let xlsx = Xlsx::new(rezip(
+                "tests/workbook01.xlsx",
+                &[("xl/workbook.xml", workbook)],
+            ))
+            .unwrap();
+            assert!(xlsx.date1904);
+            assert_eq!(xlsx.serial_to_date(0.0), (1904, 1, 1));
+            assert_eq!(xlsx.serial_to_date(42369.0), (2020, 1, 1));
+        }
+
+        #[test]
+        fn xlsb_is_unsupported() {
+            let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+            for name in ["[Content_Types].xml", "xl/workbook.bin", "xl/styles.bin"] {
+                zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            }
+            let xlsb = zip.finish().unwrap();
+
+            let actual = Xlsx::new(xlsb).err().unwrap();
+            assert!(matches!(actual, XcelmateError::UnsupportedFormat(f) if f == "xlsb"));
+        }
+    }
 }
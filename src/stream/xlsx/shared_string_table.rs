@@ -1,11 +1,11 @@
 //! The module holds all logic to fully deserialize the sharedStrings.xml in the .xlsx file
 use crate::{
     errors::XcelmateError,
-    stream::utils::{xml_reader, Key, Save, XmlWriter},
+    stream::utils::{xml_reader, Key, ReaderLimits, Save, SaveOptions, XmlWriter},
 };
 use bimap::BiBTreeMap;
 use quick_xml::{
-    events::{attributes::Attribute, BytesDecl, BytesText, Event},
+    events::{attributes::Attribute, BytesText, Event},
     name::QName,
     Reader, Writer,
 };
@@ -14,10 +14,7 @@ use std::{
     io::{BufRead, Read, Seek, Write},
     sync::Arc,
 };
-use zip::{
-    write::{FileOptionExtension, FileOptions},
-    ZipArchive,
-};
+use zip::ZipArchive;
 use super::{stylesheet::FontProperty, Stylesheet};
 
 type SharedStringRef = Arc<SharedString>;
@@ -121,6 +118,8 @@ impl<W: Write> XmlWriter<W> for StringPiece {
 pub(crate) struct SharedStringTable {
     table: BiBTreeMap<SharedStringRef, Key>,
     count: u32,
+    /// Limits enforced while reading
+    limits: ReaderLimits,
 }
 impl<W: Write> XmlWriter<W> for SharedStringTable {
     fn write_xml<'a>(
@@ -128,11 +127,6 @@ impl<W: Write> XmlWriter<W> for SharedStringTable {
         writer: &'a mut Writer<W>,
         tag_name: &str,
     ) -> Result<&'a mut Writer<W>, XcelmateError> {
-        writer.write_event(Event::Decl(BytesDecl::new(
-            "1.0",
-            Some("UTF-8"),
-            Some("yes"),
-        )))?;
         writer
             .create_element(tag_name)
             .with_attributes(vec![
@@ -165,6 +159,8 @@ impl SharedStringTable {
         };
         let mut buf = Vec::with_capacity(1024);
         let mut idx: usize = 0; // Track index for referencing updates, etc
+        // The bytes of text left before `max_string_bytes` is exceeded
+        let mut remaining_bytes = self.limits.max_string_bytes;
         loop {
             buf.clear();
             match xml.read_event_into(&mut buf) {
@@ -173,7 +169,13 @@ impl SharedStringTable {
                         if let Ok(a) = attr {
                             match a.key {
                                 QName(b"count") => {
-                                    self.count = a.unescape_value()?.parse::<u32>()?
+                                    self.count = a.unescape_value()?.parse::<u32>()?;
+                                    if self.count as usize > self.limits.max_shared_strings {
+                                        return Err(XcelmateError::LimitExceeded(format!(
+                                            "{} shared string references exceed max_shared_strings of {}",
+                                            self.count, self.limits.max_shared_strings
+                                        )));
+                                    }
                                 }
                                 // We dont care about unique count since that will be the len() of the table in SharedStringTable
                                 _ => (),
@@ -182,7 +184,15 @@ impl SharedStringTable {
                     }
                 }
                 Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"si" => {
-                    if let Some(s) = SharedStringTable::read_string(&mut xml, e.name())? {
+                    if idx >= self.limits.max_shared_strings {
+                        return Err(XcelmateError::LimitExceeded(format!(
+                            "shared strings exceed max_shared_strings of {}",
+                            self.limits.max_shared_strings
+                        )));
+                    }
+                    if let Some(s) =
+                        SharedStringTable::read_string(&mut xml, e.name(), &mut remaining_bytes)?
+                    {
                         let text = Arc::new(s);
                         self.table.insert(text, idx);
                         idx += 1;
@@ -199,9 +209,13 @@ impl SharedStringTable {
 
     // Ported from calamine https://github.com/tafia/calamine/tree/master
     /// Read either a simple or richtext string
+    ///
+    /// Every piece of text is taken out of `remaining_bytes` as soon as it is read so an
+    /// oversized string is rejected before it is built
     fn read_string<B: BufRead>(
         xml: &mut Reader<B>,
         QName(closing): QName,
+        remaining_bytes: &mut usize,
     ) -> Result<Option<SharedString>, XcelmateError> {
         let mut buf = Vec::with_capacity(1024);
         let mut val_buf = Vec::with_capacity(1024);
@@ -219,7 +233,7 @@ impl SharedStringTable {
                     }
                 }
                 Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"rPr" => {
-                    props = Some(Stylesheet::read_font(xml, e.name())?);
+                    props = Some(Stylesheet::read_font(xml, e.name(), &mut None)?);
                 }
                 Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"rPh" => {
                     is_phonetic_text = true;
@@ -229,7 +243,15 @@ impl SharedStringTable {
                     let mut value = String::new();
                     loop {
                         match xml.read_event_into(&mut val_buf)? {
-                            Event::Text(t) => value.push_str(&t.unescape()?),
+                            Event::Text(t) => {
+                                *remaining_bytes =
+                                    remaining_bytes.checked_sub(t.len()).ok_or_else(|| {
+                                        XcelmateError::LimitExceeded(
+                                            "shared strings exceed max_string_bytes".into(),
+                                        )
+                                    })?;
+                                value.push_str(&t.unescape()?)
+                            }
                             Event::End(end) if end.name() == e.name() => break,
                             Event::Eof => return Err(XcelmateError::XmlEof("t".to_string())),
                             _ => (),
@@ -293,6 +315,11 @@ impl SharedStringTable {
         }
     }
 
+    /// Set the limits enforced while reading
+    pub(crate) fn set_limits(&mut self, limits: ReaderLimits) {
+        self.limits = limits;
+    }
+
     /// Decrement the total count of all strings creation
     fn decrement_count(&mut self) {
         if self.count > 0 {
@@ -371,14 +398,14 @@ impl SharedStringTable {
     }
 }
 
-impl<W: Write + Seek, EX: FileOptionExtension> Save<W, EX> for SharedStringTable {
+impl<W: Write + Seek> Save<W> for SharedStringTable {
     fn save(
         &mut self,
         writer: &mut zip::ZipWriter<W>,
-        options: FileOptions<EX>,
+        options: &SaveOptions,
     ) -> Result<(), XcelmateError> {
-        writer.start_file("xl/sharedStrings.xml", options)?;
-        self.write_xml(&mut Writer::new(writer), "sst")?;
+        let mut xml = options.start_part(writer, "xl/sharedStrings.xml")?;
+        self.write_xml(&mut xml, "sst")?;
         Ok(())
     }
 }
@@ -387,17 +414,23 @@ impl<W: Write + Seek, EX: FileOptionExtension> Save<W, EX> for SharedStringTable
 mod shared_string_unittests {
 
     mod shared_string_api {
+        use crate::errors::XcelmateError;
         use crate::stream::{
-            utils::Save,
+            utils::{assert_xml_eq, ReaderLimits, Save, SaveOptions, XmlWriter},
             xlsx::{
                 shared_string_table::{
                     FontProperty, SharedString, SharedStringTable, StringPiece, StringType,
                 },
-                stylesheet::{Color, FormatState, Rgb},
+                stylesheet::{Color, FontFamily, FontScheme, FormatState, Rgb},
             },
         };
-        use std::{fs::File, io::Cursor, sync::Arc};
-        use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+        use quick_xml::Writer;
+        use std::{
+            fs::File,
+            io::{Cursor, Read, Write},
+            sync::Arc,
+        };
+        use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
 
         fn init(path: &str) -> SharedStringTable {
             let file = File::open(path).unwrap();
@@ -421,10 +454,10 @@ mod shared_string_unittests {
                         underline: FormatState::Enabled,
                         italic: FormatState::Enabled,
                         size: "11".into(),
-                        color: Color::Theme { id: 1, tint: None },
+                        color: Some(Color::Theme { id: 1, tint: None }),
                         font: "Calibri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     value: StringType::NoPreserve("big".into()),
@@ -433,10 +466,10 @@ mod shared_string_unittests {
                     props: Some(FontProperty {
                         bold: FormatState::Enabled,
                         size: "11".into(),
-                        color: Color::Theme { id: 1, tint: None },
+                        color: Some(Color::Theme { id: 1, tint: None }),
                         font: "Calibri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     value: StringType::Preserve(" example".into()),
@@ -444,10 +477,10 @@ mod shared_string_unittests {
                 StringPiece {
                     props: Some(FontProperty {
                         size: "11".into(),
-                        color: Color::Theme { id: 1, tint: None },
+                        color: Some(Color::Theme { id: 1, tint: None }),
                         font: "Calibri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     value: StringType::Preserve(" ".into()),
@@ -456,10 +489,10 @@ mod shared_string_unittests {
                     props: Some(FontProperty {
                         italic: FormatState::Enabled,
                         size: "11".into(),
-                        color: Color::Theme { id: 1, tint: None },
+                        color: Some(Color::Theme { id: 1, tint: None }),
                         font: "Calibri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     value: StringType::Preserve("of ".into()),
@@ -468,10 +501,10 @@ mod shared_string_unittests {
                     props: Some(FontProperty {
                         underline: FormatState::Enabled,
                         size: "11".into(),
-                        color: Color::Theme { id: 1, tint: None },
+                        color: Some(Color::Theme { id: 1, tint: None }),
                         font: "Calibri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     value: StringType::NoPreserve("some".into()),
@@ -479,10 +512,10 @@ mod shared_string_unittests {
                 StringPiece {
                     props: Some(FontProperty {
                         size: "11".into(),
-                        color: Color::Theme { id: 1, tint: None },
+                        color: Some(Color::Theme { id: 1, tint: None }),
                         font: "Calibri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     value: StringType::Preserve(" ".into()),
@@ -490,13 +523,13 @@ mod shared_string_unittests {
                 StringPiece {
                     props: Some(FontProperty {
                         size: "11".into(),
-                        color: Color::Theme {
+                        color: Some(Color::Theme {
                             id: 5,
                             tint: Some("0.39997558519241921".into()),
-                        },
+                        }),
                         font: "Calibri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     value: StringType::NoPreserve("rich".into()),
@@ -504,10 +537,10 @@ mod shared_string_unittests {
                 StringPiece {
                     props: Some(FontProperty {
                         size: "11".into(),
-                        color: Color::Theme { id: 1, tint: None },
+                        color: Some(Color::Theme { id: 1, tint: None }),
                         font: "Calibri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     value: StringType::Preserve(" ".into()),
@@ -516,10 +549,10 @@ mod shared_string_unittests {
                     props: Some(FontProperty {
                         double: FormatState::Enabled,
                         size: "11".into(),
-                        color: Color::Theme { id: 1, tint: None },
+                        color: Some(Color::Theme { id: 1, tint: None }),
                         font: "Calibri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     value: StringType::NoPreserve("text".into()),
@@ -527,10 +560,10 @@ mod shared_string_unittests {
                 StringPiece {
                     props: Some(FontProperty {
                         size: "11".into(),
-                        color: Color::Theme { id: 1, tint: None },
+                        color: Some(Color::Theme { id: 1, tint: None }),
                         font: "Calibri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     value: StringType::Preserve(" ".into()),
@@ -538,10 +571,10 @@ mod shared_string_unittests {
                 StringPiece {
                     props: Some(FontProperty {
                         size: "11".into(),
-                        color: Color::Rgb(Rgb::Custom(186, 155, 203)),
+                        color: Some(Color::Rgb(Rgb::Custom(186, 155, 203))),
                         font: "Calibri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     value: StringType::NoPreserve("here".into()),
@@ -569,10 +602,10 @@ mod shared_string_unittests {
 
                         italic: FormatState::Enabled,
                         size: "11".into(),
-                        color: Color::Theme { id: 1, tint: None },
+                        color: Some(Color::Theme { id: 1, tint: None }),
                         font: "Calibri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     value: StringType::NoPreserve("big".into()),
@@ -581,10 +614,10 @@ mod shared_string_unittests {
                     props: Some(FontProperty {
                         bold: FormatState::Enabled,
                         size: "11".into(),
-                        color: Color::Theme { id: 1, tint: None },
+                        color: Some(Color::Theme { id: 1, tint: None }),
                         font: "Calibri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     value: StringType::Preserve(" example".into()),
@@ -592,10 +625,10 @@ mod shared_string_unittests {
                 StringPiece {
                     props: Some(FontProperty {
                         size: "11".into(),
-                        color: Color::Theme { id: 1, tint: None },
+                        color: Some(Color::Theme { id: 1, tint: None }),
                         font: "Calibri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     value: StringType::Preserve(" ".into()),
@@ -604,10 +637,10 @@ mod shared_string_unittests {
                     props: Some(FontProperty {
                         italic: FormatState::Enabled,
                         size: "11".into(),
-                        color: Color::Theme { id: 1, tint: None },
+                        color: Some(Color::Theme { id: 1, tint: None }),
                         font: "Calibri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     value: StringType::Preserve("of ".into()),
@@ -616,10 +649,10 @@ mod shared_string_unittests {
                     props: Some(FontProperty {
                         underline: FormatState::Enabled,
                         size: "11".into(),
-                        color: Color::Theme { id: 1, tint: None },
+                        color: Some(Color::Theme { id: 1, tint: None }),
                         font: "Calibri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     value: StringType::NoPreserve("some".into()),
@@ -627,10 +660,10 @@ mod shared_string_unittests {
                 StringPiece {
                     props: Some(FontProperty {
                         size: "11".into(),
-                        color: Color::Theme { id: 1, tint: None },
+                        color: Some(Color::Theme { id: 1, tint: None }),
                         font: "Calibri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     value: StringType::Preserve(" ".into()),
@@ -638,13 +671,13 @@ mod shared_string_unittests {
                 StringPiece {
                     props: Some(FontProperty {
                         size: "11".into(),
-                        color: Color::Theme {
+                        color: Some(Color::Theme {
                             id: 5,
                             tint: Some("0.39997558519241921".into()),
-                        },
+                        }),
                         font: "Calibri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     value: StringType::NoPreserve("rich".into()),
@@ -652,10 +685,10 @@ mod shared_string_unittests {
                 StringPiece {
                     props: Some(FontProperty {
                         size: "11".into(),
-                        color: Color::Theme { id: 1, tint: None },
+                        color: Some(Color::Theme { id: 1, tint: None }),
                         font: "Calibri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     value: StringType::Preserve(" ".into()),
@@ -664,10 +697,10 @@ mod shared_string_unittests {
                     props: Some(FontProperty {
                         double: FormatState::Enabled,
                         size: "11".into(),
-                        color: Color::Theme { id: 1, tint: None },
+                        color: Some(Color::Theme { id: 1, tint: None }),
                         font: "Calibri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     value: StringType::NoPreserve("text".into()),
@@ -675,10 +708,10 @@ mod shared_string_unittests {
                 StringPiece {
                     props: Some(FontProperty {
                         size: "11".into(),
-                        color: Color::Theme { id: 1, tint: None },
+                        color: Some(Color::Theme { id: 1, tint: None }),
                         font: "Calibri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     value: StringType::Preserve(" ".into()),
@@ -686,10 +719,10 @@ mod shared_string_unittests {
                 StringPiece {
                     props: Some(FontProperty {
                         size: "11".into(),
-                        color: Color::Rgb(Rgb::Custom(186, 155, 203)),
+                        color: Some(Color::Rgb(Rgb::Custom(186, 155, 203))),
                         font: "Calibrri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     value: StringType::NoPreserve("here".into()),
@@ -788,14 +821,83 @@ mod shared_string_unittests {
         fn save_file() {
             let mut sst = init("tests/workbook01.xlsx");
             let mut zip = ZipWriter::new(Cursor::new(Vec::<u8>::new()));
-            sst.save(
-                &mut zip,
-                SimpleFileOptions::default().compression_method(CompressionMethod::Deflated),
-            )
-            .unwrap();
+            sst.save(&mut zip, &SaveOptions::default()).unwrap();
 
             // Verify all data is written
             assert_eq!(zip.finish().unwrap().into_inner().len(), 479);
         }
+
+        #[test]
+        fn read_with_limits() {
+            let read = |limits: ReaderLimits| {
+                let file = File::open("tests/workbook04.xlsx").unwrap();
+                let mut zip = ZipArchive::new(file).unwrap();
+                let mut sst = SharedStringTable::default();
+                sst.set_limits(limits);
+                sst.read_shared_strings(&mut zip)
+            };
+
+            let actual = read(ReaderLimits {
+                max_shared_strings: 10,
+                ..Default::default()
+            });
+            assert!(matches!(actual, Err(XcelmateError::LimitExceeded(_))));
+
+            let actual = read(ReaderLimits {
+                max_string_bytes: 10,
+                ..Default::default()
+            });
+            assert!(matches!(actual, Err(XcelmateError::LimitExceeded(_))));
+
+            assert!(read(ReaderLimits::default()).is_ok());
+        }
+
+        #[test]
+        fn read_with_string_bytes_limit() {
+            let read = |max_string_bytes: usize| {
+                let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+                zip.start_file("xl/sharedStrings.xml", SimpleFileOptions::default())
+                    .unwrap();
+                zip.write_all(br#"<sst count="1" uniqueCount="1"><si><r><t>0123456789</t></r><r><t>0123456789</t></r></si></sst>"#)
+                    .unwrap();
+                let mut zip = ZipArchive::new(zip.finish().unwrap()).unwrap();
+                let mut sst = SharedStringTable::default();
+                sst.set_limits(ReaderLimits {
+                    max_string_bytes,
+                    ..Default::default()
+                });
+                sst.read_shared_strings(&mut zip)
+            };
+
+            // The pieces of a single rich text string count towards the limit
+            assert!(matches!(read(19), Err(XcelmateError::LimitExceeded(_))));
+            assert!(read(20).is_ok());
+        }
+
+        #[test]
+        fn round_trip() {
+            for path in ["tests/workbook01.xlsx", "tests/workbook04.xlsx"] {
+                let mut zip = ZipArchive::new(File::open(path).unwrap()).unwrap();
+                let mut expected = Vec::new();
+                zip.by_name("xl/sharedStrings.xml")
+                    .unwrap()
+                    .read_to_end(&mut expected)
+                    .unwrap();
+
+                let sst = init(path);
+                let mut writer = Writer::new(Cursor::new(Vec::new()));
+                sst.write_xml(&mut writer, "sst").unwrap();
+                assert_xml_eq(&writer.into_inner().into_inner(), &expected);
+            }
+        }
+
+        #[test]
+        #[should_panic(expected = "xml differs")]
+        fn round_trip_detects_lost_padding() {
+            assert_xml_eq(
+                b"<si><t xml:space=\"preserve\">padded</t></si>",
+                b"<si>\n  <t xml:space=\"preserve\">  padded  </t>\n</si>",
+            );
+        }
     }
 }
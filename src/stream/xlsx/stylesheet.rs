@@ -1,28 +1,33 @@
 use crate::{
-    errors::XcelmateError,
-    stream::utils::{xml_reader, Key, Save, XmlWriter},
+    errors::{Warning, XcelmateError},
+    stream::utils::{generate_uid, xml_reader, Key, Save, SaveOptions, XmlWriter},
 };
-use bimap::{BiBTreeMap, BiHashMap, BiMap};
+
+use super::theme::Theme;
 use quick_xml::{
-    events::{BytesDecl, BytesStart, Event},
+    events::{attributes::Attribute, BytesStart, Event},
     name::QName,
     Reader, Writer,
 };
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet},
+    hash::Hash,
     io::{BufRead, Read, Seek, Write},
     ops::RangeInclusive,
     sync::Arc,
 };
-use zip::{
-    write::{FileOptionExtension, FileOptions},
-    ZipArchive,
-};
+use zip::ZipArchive;
+
+/// The path of the stylesheet part within the archive
+const STYLESHEET_PATH: &str = "xl/styles.xml";
 
 /// The `Rgb` promotes better api usage with hexadecimal coloring
 #[derive(Debug, PartialEq, Clone, Eq, PartialOrd, Hash, Ord)]
 pub(crate) enum Rgb {
+    /// An opaque color
     Custom(u8, u8, u8),
+    /// A color with its alpha channel leading as in `ARGB`
+    CustomAlpha(u8, u8, u8, u8),
 }
 impl ToString for Rgb {
     fn to_string(&self) -> String {
@@ -33,9 +38,84 @@ impl ToString for Rgb {
                 &format!("{:02X}", g),
                 &format!("{:02X}", b)
             ),
+            Rgb::CustomAlpha(a, r, g, b) => {
+                format!("{:02X}", a) + &Stylesheet::from_rgb(*r, *g, *b)
+            }
         }
     }
 }
+/// The legacy palette that `indexed` colors refer to unless the workbook overrides it
+const LEGACY_PALETTE: [(u8, u8, u8); 64] = [
+    (0x00, 0x00, 0x00),
+    (0xFF, 0xFF, 0xFF),
+    (0xFF, 0x00, 0x00),
+    (0x00, 0xFF, 0x00),
+    (0x00, 0x00, 0xFF),
+    (0xFF, 0xFF, 0x00),
+    (0xFF, 0x00, 0xFF),
+    (0x00, 0xFF, 0xFF),
+    (0x00, 0x00, 0x00),
+    (0xFF, 0xFF, 0xFF),
+    (0xFF, 0x00, 0x00),
+    (0x00, 0xFF, 0x00),
+    (0x00, 0x00, 0xFF),
+    (0xFF, 0xFF, 0x00),
+    (0xFF, 0x00, 0xFF),
+    (0x00, 0xFF, 0xFF),
+    (0x80, 0x00, 0x00),
+    (0x00, 0x80, 0x00),
+    (0x00, 0x00, 0x80),
+    (0x80, 0x80, 0x00),
+    (0x80, 0x00, 0x80),
+    (0x00, 0x80, 0x80),
+    (0xC0, 0xC0, 0xC0),
+    (0x80, 0x80, 0x80),
+    (0x99, 0x99, 0xFF),
+    (0x99, 0x33, 0x66),
+    (0xFF, 0xFF, 0xCC),
+    (0xCC, 0xFF, 0xFF),
+    (0x66, 0x00, 0x66),
+    (0xFF, 0x80, 0x80),
+    (0x00, 0x66, 0xCC),
+    (0xCC, 0xCC, 0xFF),
+    (0x00, 0x00, 0x80),
+    (0xFF, 0x00, 0xFF),
+    (0xFF, 0xFF, 0x00),
+    (0x00, 0xFF, 0xFF),
+    (0x80, 0x00, 0x80),
+    (0x80, 0x00, 0x00),
+    (0x00, 0x80, 0x80),
+    (0x00, 0x00, 0xFF),
+    (0x00, 0xCC, 0xFF),
+    (0xCC, 0xFF, 0xFF),
+    (0xCC, 0xFF, 0xCC),
+    (0xFF, 0xFF, 0x99),
+    (0x99, 0xCC, 0xFF),
+    (0xFF, 0x99, 0xCC),
+    (0xCC, 0x99, 0xFF),
+    (0xFF, 0xCC, 0x99),
+    (0x33, 0x66, 0xFF),
+    (0x33, 0xCC, 0xCC),
+    (0x99, 0xCC, 0x00),
+    (0xFF, 0xCC, 0x00),
+    (0xFF, 0x99, 0x00),
+    (0xFF, 0x66, 0x00),
+    (0x66, 0x66, 0x99),
+    (0x96, 0x96, 0x96),
+    (0x00, 0x33, 0x66),
+    (0x33, 0x99, 0x66),
+    (0x00, 0x33, 0x00),
+    (0x33, 0x33, 0x00),
+    (0x99, 0x33, 0x00),
+    (0x99, 0x33, 0x66),
+    (0x33, 0x33, 0x99),
+    (0x33, 0x33, 0x33),
+];
+/// The indexed color for the system foreground, which is black
+const SYSTEM_FOREGROUND_INDEX: u32 = 64;
+/// The indexed color for the system background, which is white
+const SYSTEM_BACKGROUND_INDEX: u32 = 65;
+
 /// The `Color` denotes the type of coloring system to
 /// use since excel has builtin coloring to choose that will map to `theme` but
 /// for custom specfic coloring `rgb` is used
@@ -51,7 +131,25 @@ pub(crate) enum Color {
     /// RGB color model
     Rgb(Rgb),
     Index(u32),
-    Auto(u32),
+    /// The automatic color chosen by the application, written as `auto="1"`
+    Auto,
+}
+impl Color {
+    /// The RGB this color displays as, with the tint of theme colors applied
+    ///
+    /// # Returns
+    /// `None` for automatic colors which the application picks, or when the theme has no
+    /// color at the index
+    pub(crate) fn to_rgb(&self, stylesheet: &Stylesheet, theme: &Theme) -> Option<Rgb> {
+        match self {
+            Color::Theme { id, tint } => theme
+                .color(*id)
+                .map(|_| theme.resolve(*id, tint.as_deref())),
+            Color::Rgb(rgb) => Some(rgb.clone()),
+            Color::Index(idx) => Some(stylesheet.resolve_indexed_color(*idx)),
+            Color::Auto => None,
+        }
+    }
 }
 impl Default for Color {
     fn default() -> Self {
@@ -83,8 +181,8 @@ impl<W: Write> XmlWriter<W> for Color {
                 let writer = writer.with_attribute(("indexed", idx.to_string().as_str()));
                 Ok(writer.write_empty()?)
             }
-            Color::Auto(val) => {
-                let writer = writer.with_attribute(("auto", val.to_string().as_str()));
+            Color::Auto => {
+                let writer = writer.with_attribute(("auto", "1"));
                 Ok(writer.write_empty()?)
             }
         }
@@ -103,6 +201,105 @@ pub(crate) enum FormatState {
     /// Value will not show
     None,
 }
+impl From<Option<bool>> for FormatState {
+    fn from(value: Option<bool>) -> Self {
+        match value {
+            Some(true) => FormatState::Enabled,
+            Some(false) => FormatState::Disabled,
+            None => FormatState::None,
+        }
+    }
+}
+impl From<FormatState> for Option<bool> {
+    fn from(value: FormatState) -> Self {
+        match value {
+            FormatState::Enabled => Some(true),
+            FormatState::Disabled => Some(false),
+            FormatState::None => None,
+        }
+    }
+}
+
+/// The font scheme a font belongs to so it follows the theme's major (headings) or minor (body) font
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Hash, Ord)]
+pub(crate) enum FontScheme {
+    /// Font is explicitly not part of the theme
+    None,
+    /// Theme major font
+    Major,
+    /// Theme minor font
+    Minor,
+}
+impl ToString for FontScheme {
+    fn to_string(&self) -> String {
+        match self {
+            FontScheme::None => "none".into(),
+            FontScheme::Major => "major".into(),
+            FontScheme::Minor => "minor".into(),
+        }
+    }
+}
+impl TryFrom<&str> for FontScheme {
+    type Error = XcelmateError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "none" => Ok(FontScheme::None),
+            "major" => Ok(FontScheme::Major),
+            "minor" => Ok(FontScheme::Minor),
+            _ => Err(XcelmateError::MissingVariant(
+                "FontScheme".into(),
+                value.into(),
+            )),
+        }
+    }
+}
+
+/// The font family classification used by Excel when the named font is unavailable
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Hash, Ord)]
+pub(crate) enum FontFamily {
+    #[default]
+    /// Family not applicable and will not show
+    NotApplicable,
+    /// Variable width with serifs
+    Roman,
+    /// Variable width without serifs
+    Swiss,
+    /// Fixed width
+    Modern,
+    /// Cursive
+    Script,
+    /// Novelty
+    Decorative,
+    /// Out of range value kept as read so it is written back unchanged
+    Other(u32),
+}
+impl From<u32> for FontFamily {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => FontFamily::NotApplicable,
+            1 => FontFamily::Roman,
+            2 => FontFamily::Swiss,
+            3 => FontFamily::Modern,
+            4 => FontFamily::Script,
+            5 => FontFamily::Decorative,
+            v => FontFamily::Other(v),
+        }
+    }
+}
+impl From<&FontFamily> for u32 {
+    fn from(value: &FontFamily) -> Self {
+        match value {
+            FontFamily::NotApplicable => 0,
+            FontFamily::Roman => 1,
+            FontFamily::Swiss => 2,
+            FontFamily::Modern => 3,
+            FontFamily::Script => 4,
+            FontFamily::Decorative => 5,
+            FontFamily::Other(v) => *v,
+        }
+    }
+}
 
 /// The `FontProperty` denotes all styling options
 /// that can be added to text
@@ -120,17 +317,33 @@ pub(crate) struct FontProperty {
     pub(crate) double: FormatState,
     pub(crate) italic: FormatState,
     pub(crate) size: String,
-    pub(crate) color: Color,
+    /// `None` when the font does not specify a color
+    pub(crate) color: Option<Color>,
     /// Font type
     pub(crate) font: String,
     /// Font family
-    pub(crate) family: u32,
-    /// Font scheme
-    pub(crate) scheme: String,
+    pub(crate) family: FontFamily,
+    /// Font scheme, absent when the font has no `<scheme>`
+    pub(crate) scheme: Option<FontScheme>,
     /// Allow duplicate with counter since it will always hash different
     pub(crate) dup_cnt: usize,
 }
 
+impl FontProperty {
+    /// Set the font family, only the values 0-5 are defined
+    pub(crate) fn set_family(&mut self, family: u32) -> Result<(), XcelmateError> {
+        match FontFamily::from(family) {
+            FontFamily::Other(v) => Err(XcelmateError::MissingVariant(
+                "FontFamily".into(),
+                v.to_string(),
+            )),
+            family => {
+                self.family = family;
+                Ok(())
+            }
+        }
+    }
+}
 impl<W: Write> XmlWriter<W> for FontProperty {
     fn write_xml<'a>(
         &self,
@@ -213,23 +426,25 @@ impl<W: Write> XmlWriter<W> for FontProperty {
                         .with_attribute(("val", self.size.as_str()))
                         .write_empty()?;
                 }
-                self.color.write_xml(writer, "color")?;
+                if let Some(color) = &self.color {
+                    color.write_xml(writer, "color")?;
+                }
                 if !self.font.is_empty() {
                     writer
                         .create_element(if tag_name == "font" { "name" } else { "rFont" }) //the similarity of rich text and font tags are identical except for this
                         .with_attribute(("val", self.font.as_str()))
                         .write_empty()?;
                 }
-                if self.family != u32::default() {
+                if self.family != FontFamily::NotApplicable {
                     writer
                         .create_element("family")
-                        .with_attribute(("val", self.family.to_string().as_str()))
+                        .with_attribute(("val", u32::from(&self.family).to_string().as_str()))
                         .write_empty()?;
                 }
-                if !self.scheme.is_empty() {
+                if let Some(scheme) = &self.scheme {
                     writer
                         .create_element("scheme")
-                        .with_attribute(("val", self.scheme.as_str()))
+                        .with_attribute(("val", scheme.to_string().as_str()))
                         .write_empty()?;
                 }
                 Ok(())
@@ -243,12 +458,99 @@ impl<W: Write> XmlWriter<W> for FontProperty {
 const LOCALIZED_RANGE_NUMBER_FORMAT: RangeInclusive<usize> = 41..=44;
 /// The highest reserved id for number formats before custom number formats are detected
 const MAX_RESERVED_NUMBER_FORMAT: usize = 163;
+/// Whether a number format id must be declared in `numFmts` rather than being builtin
+fn is_custom_number_format(id: Key) -> bool {
+    LOCALIZED_RANGE_NUMBER_FORMAT.contains(&id) || id > MAX_RESERVED_NUMBER_FORMAT
+}
+/// The builtin number formats Excel knows by id without a `numFmts` declaration
+const BUILTIN_NUMBER_FORMATS: [(u32, &str); 28] = [
+    (0, "General"),
+    (1, "0"),
+    (2, "0.00"),
+    (3, "#,##0"),
+    (4, "#,##0.00"),
+    (9, "0%"),
+    (10, "0.00%"),
+    (11, "0.00E+00"),
+    (12, "# ?/?"),
+    (13, "# ??/??"),
+    (14, "mm-dd-yy"),
+    (15, "d-mmm-yy"),
+    (16, "d-mmm"),
+    (17, "mmm-yy"),
+    (18, "h:mm AM/PM"),
+    (19, "h:mm:ss AM/PM"),
+    (20, "h:mm"),
+    (21, "h:mm:ss"),
+    (22, "m/d/yy h:mm"),
+    (37, "#,##0 ;(#,##0)"),
+    (38, "#,##0 ;[Red](#,##0)"),
+    (39, "#,##0.00;(#,##0.00)"),
+    (40, "#,##0.00;[Red](#,##0.00)"),
+    (45, "mm:ss"),
+    (46, "[h]:mm:ss"),
+    (47, "mmss.0"),
+    (48, "##0.0E+0"),
+    (49, "@"),
+];
 /// The formatting style to use on numbers
-#[derive(Debug, PartialEq, Default, Clone, Eq, PartialOrd, Hash, Ord)]
+#[derive(Debug, PartialEq, Clone, Eq, PartialOrd, Hash, Ord)]
 pub(crate) struct NumberFormat {
-    id: u32,
+    /// Custom formats created without an id get the next free custom id once added to the
+    /// stylesheet
+    id: Option<u32>,
     format_code: String,
 }
+impl NumberFormat {
+    /// Create a number format, rejecting codes that are empty or have unbalanced quotes or
+    /// brackets
+    pub(crate) fn new(id: u32, format_code: &str) -> Result<Self, XcelmateError> {
+        Ok(Self {
+            id: Some(id),
+            ..NumberFormat::custom(format_code)?
+        })
+    }
+
+    /// Create a custom number format that is given the next free custom id once added to the
+    /// stylesheet
+    pub(crate) fn custom(format_code: &str) -> Result<Self, XcelmateError> {
+        if format_code.is_empty() {
+            return Err(XcelmateError::InvalidNumberFormat(format_code.into()));
+        }
+        let mut chars = format_code.chars();
+        let mut in_quote = false;
+        let mut in_bracket = false;
+        let mut balanced = true;
+        while let Some(c) = chars.next() {
+            match c {
+                // Escaped characters are always literal
+                '\\' if !in_quote => balanced &= chars.next().is_some(),
+                '"' => in_quote = !in_quote,
+                '[' if !in_quote => {
+                    balanced &= !in_bracket;
+                    in_bracket = true
+                }
+                ']' if !in_quote => {
+                    balanced &= in_bracket;
+                    in_bracket = false
+                }
+                _ => (),
+            }
+        }
+        if !balanced || in_quote || in_bracket {
+            return Err(XcelmateError::InvalidNumberFormat(format_code.into()));
+        }
+        Ok(Self {
+            id: None,
+            format_code: format_code.into(),
+        })
+    }
+
+    /// The id cells reference the format by, which is `General` until one is allocated
+    fn id(&self) -> u32 {
+        self.id.unwrap_or_default()
+    }
+}
 impl<W: Write> XmlWriter<W> for NumberFormat {
     fn write_xml<'a>(
         &self,
@@ -258,7 +560,7 @@ impl<W: Write> XmlWriter<W> for NumberFormat {
         writer
             .create_element(tag_name)
             .with_attributes(vec![
-                ("numFmtId", self.id.to_string().as_str()),
+                ("numFmtId", self.id().to_string().as_str()),
                 ("formatCode", self.format_code.as_str()),
             ])
             .write_empty()?;
@@ -272,7 +574,69 @@ enum PatternFill {
     #[default]
     None,
     Solid,
+    /// 12.5% gray
     Gray,
+    /// 6.25% gray
+    Gray0625,
+    MediumGray,
+    DarkGray,
+    LightGray,
+    DarkHorizontal,
+    DarkVertical,
+    DarkDown,
+    DarkUp,
+    DarkGrid,
+    DarkTrellis,
+    LightHorizontal,
+    LightVertical,
+    LightDown,
+    LightUp,
+    LightGrid,
+    LightTrellis,
+}
+const PATTERN_FILLS: [(PatternFill, &str); 19] = [
+    (PatternFill::None, "none"),
+    (PatternFill::Solid, "solid"),
+    (PatternFill::Gray, "gray125"),
+    (PatternFill::Gray0625, "gray0625"),
+    (PatternFill::MediumGray, "mediumGray"),
+    (PatternFill::DarkGray, "darkGray"),
+    (PatternFill::LightGray, "lightGray"),
+    (PatternFill::DarkHorizontal, "darkHorizontal"),
+    (PatternFill::DarkVertical, "darkVertical"),
+    (PatternFill::DarkDown, "darkDown"),
+    (PatternFill::DarkUp, "darkUp"),
+    (PatternFill::DarkGrid, "darkGrid"),
+    (PatternFill::DarkTrellis, "darkTrellis"),
+    (PatternFill::LightHorizontal, "lightHorizontal"),
+    (PatternFill::LightVertical, "lightVertical"),
+    (PatternFill::LightDown, "lightDown"),
+    (PatternFill::LightUp, "lightUp"),
+    (PatternFill::LightGrid, "lightGrid"),
+    (PatternFill::LightTrellis, "lightTrellis"),
+];
+impl ToString for PatternFill {
+    fn to_string(&self) -> String {
+        PATTERN_FILLS
+            .iter()
+            .find(|(pattern, _)| pattern == self)
+            .map(|(_, val)| val.to_string())
+            .expect("all pattern fills should be mapped")
+    }
+}
+impl TryFrom<&str> for PatternFill {
+    type Error = XcelmateError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        PATTERN_FILLS
+            .iter()
+            .find(|(_, val)| *val == value)
+            .map(|(pattern, _)| pattern.clone())
+            .ok_or(XcelmateError::MissingVariant(
+                "PatternFill".into(),
+                value.into(),
+            ))
+    }
 }
 impl<W: Write> XmlWriter<W> for PatternFill {
     fn write_xml<'a>(
@@ -280,17 +644,10 @@ impl<W: Write> XmlWriter<W> for PatternFill {
         writer: &'a mut Writer<W>,
         tag_name: &'a str,
     ) -> Result<&'a mut Writer<W>, XcelmateError> {
-        match self {
-            PatternFill::None => Ok(writer
-                .create_element(tag_name)
-                .with_attribute(("patternType", "none"))
-                .write_empty()?),
-            PatternFill::Gray => Ok(writer
-                .create_element(tag_name)
-                .with_attribute(("patternType", "gray125"))
-                .write_empty()?),
-            _ => Ok(writer),
-        }
+        Ok(writer
+            .create_element(tag_name)
+            .with_attribute(("patternType", self.to_string().as_str()))
+            .write_empty()?)
     }
 }
 
@@ -312,20 +669,32 @@ impl<W: Write> XmlWriter<W> for Fill {
             .write_inner_content::<_, XcelmateError>(|writer| {
                 let writer_fill = writer.create_element("patternFill");
                 match (&self.r#type, &self.background, &self.foreground) {
-                    (PatternFill::None, Some(bg), Some(fg)) => writer_fill
-                        .write_inner_content::<_, XcelmateError>(|writer| {
-                            fg.write_xml(writer, "fgColor")?;
-                            bg.write_xml(writer, "bgColor")?;
+                    // Differential fills such as conditional format highlights leave the
+                    // pattern out and Excel stops drawing them when it is `none`
+                    (PatternFill::None, bg, fg) if bg.is_some() || fg.is_some() => {
+                        writer_fill.write_inner_content::<_, XcelmateError>(|writer| {
+                            if let Some(fg) = fg {
+                                fg.write_xml(writer, "fgColor")?;
+                            }
+                            if let Some(bg) = bg {
+                                bg.write_xml(writer, "bgColor")?;
+                            }
                             Ok(())
-                        })?,
-                    (PatternFill::Solid, Some(bg), Some(fg)) => writer_fill
-                        .with_attribute(("patternType", "solid"))
+                        })?
+                    }
+                    (r#type, None, None) => r#type.write_xml(writer, "patternFill")?,
+                    // Patterns such as solid fills commonly only carry a foreground
+                    (r#type, bg, fg) => writer_fill
+                        .with_attribute(("patternType", r#type.to_string().as_str()))
                         .write_inner_content::<_, XcelmateError>(|writer| {
-                            fg.write_xml(writer, "fgColor")?;
-                            bg.write_xml(writer, "bgColor")?;
+                            if let Some(fg) = fg {
+                                fg.write_xml(writer, "fgColor")?;
+                            }
+                            if let Some(bg) = bg {
+                                bg.write_xml(writer, "bgColor")?;
+                            }
                             Ok(())
                         })?,
-                    _ => self.r#type.write_xml(writer, tag_name)?,
                 };
                 Ok(())
             });
@@ -333,9 +702,25 @@ impl<W: Write> XmlWriter<W> for Fill {
     }
 }
 
+impl Fill {
+    /// Excel pairs solid fills with its default `indexed="64"` (system foreground) background
+    /// when no background is specified, omitting it changes the rendering in some viewers
+    fn with_default_background(&self) -> Fill {
+        match (&self.r#type, &self.background, &self.foreground) {
+            (PatternFill::Solid, None, Some(_)) => Fill {
+                background: Some(Color::Index(SYSTEM_FOREGROUND_INDEX)),
+                ..self.clone()
+            },
+            _ => self.clone(),
+        }
+    }
+}
+
 /// The type of line styling for a border
 #[derive(Debug, PartialEq, Clone, Eq, PartialOrd, Hash, Ord)]
 enum BorderStyle {
+    /// No border, used to explicitly clear a region
+    None,
     /// Thin border
     Thin,
     /// Medium border
@@ -363,23 +748,44 @@ enum BorderStyle {
     /// Medium dash-dot-dot border
     MediumDashDotDot,
 }
+/// The single mapping between `BorderStyle` and its xml value so reading and writing can not drift
+const BORDER_STYLES: [(BorderStyle, &str); 14] = [
+    (BorderStyle::None, "none"),
+    (BorderStyle::Thin, "thin"),
+    (BorderStyle::Medium, "medium"),
+    (BorderStyle::Thick, "thick"),
+    (BorderStyle::Double, "double"),
+    (BorderStyle::Dashed, "dashed"),
+    (BorderStyle::Dotted, "dotted"),
+    (BorderStyle::DashDot, "dashDot"),
+    (BorderStyle::DashDotDot, "dashDotDot"),
+    (BorderStyle::SlantDashDot, "slantDashDot"),
+    (BorderStyle::Hair, "hair"),
+    (BorderStyle::MediumDashed, "mediumDashed"),
+    (BorderStyle::MediumDashDot, "mediumDashDot"),
+    (BorderStyle::MediumDashDotDot, "mediumDashDotDot"),
+];
 impl ToString for BorderStyle {
     fn to_string(&self) -> String {
-        match self {
-            BorderStyle::Thin => "thin".into(),
-            BorderStyle::Medium => "medium".into(),
-            BorderStyle::Thick => "thick".into(),
-            BorderStyle::Double => "double".into(),
-            BorderStyle::Dashed => "dashed".into(),
-            BorderStyle::Dotted => "dotted".into(),
-            BorderStyle::DashDot => "dashDot".into(),
-            BorderStyle::DashDotDot => "dashDotDot".into(),
-            BorderStyle::SlantDashDot => "slantDashDot".into(),
-            BorderStyle::Hair => "hair".into(),
-            BorderStyle::MediumDashed => "mediumDashed".into(),
-            BorderStyle::MediumDashDot => "mediumDashDot".into(),
-            BorderStyle::MediumDashDotDot => "mediumDashDotDot".into(),
-        }
+        BORDER_STYLES
+            .iter()
+            .find(|(style, _)| style == self)
+            .map(|(_, val)| val.to_string())
+            .expect("all border styles should be mapped")
+    }
+}
+impl TryFrom<&str> for BorderStyle {
+    type Error = XcelmateError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        BORDER_STYLES
+            .iter()
+            .find(|(_, val)| *val == value)
+            .map(|(style, _)| style.clone())
+            .ok_or(XcelmateError::MissingVariant(
+                "BorderStyle".into(),
+                value.into(),
+            ))
     }
 }
 /// The border region to apply styling to
@@ -394,17 +800,21 @@ impl<W: Write> XmlWriter<W> for BorderRegion {
         writer: &'a mut Writer<W>,
         tag_name: &'a str,
     ) -> Result<&'a mut Writer<W>, XcelmateError> {
-        if let (Some(style), Some(color)) = (&self.style, &self.color) {
-            let writer = writer
+        match (&self.style, &self.color) {
+            (Some(style), Some(color)) => Ok(writer
                 .create_element(tag_name)
                 .with_attribute(("style", style.to_string().as_str()))
                 .write_inner_content::<_, XcelmateError>(|writer| {
                     color.write_xml(writer, "color")?;
                     Ok(())
-                });
-            return Ok(writer?);
+                })?),
+            // A style without coloring such as `none` that clears the region
+            (Some(style), None) => Ok(writer
+                .create_element(tag_name)
+                .with_attribute(("style", style.to_string().as_str()))
+                .write_empty()?),
+            _ => Ok(writer),
         }
-        Ok(writer)
     }
 }
 /// The styling for all border regions of a cell
@@ -417,6 +827,10 @@ pub(crate) struct Border {
     diagonal: BorderRegion,
     vertical: BorderRegion,
     horizontal: BorderRegion,
+    /// The diagonal runs from the bottom left to the top right
+    diagonal_up: bool,
+    /// The diagonal runs from the top left to the bottom right
+    diagonal_down: bool,
 }
 impl<W: Write> XmlWriter<W> for Border {
     fn write_xml<'a>(
@@ -424,16 +838,24 @@ impl<W: Write> XmlWriter<W> for Border {
         writer: &'a mut Writer<W>,
         tag_name: &'a str,
     ) -> Result<&'a mut Writer<W>, XcelmateError> {
+        let mut attrs = Vec::new();
+        if self.diagonal_up {
+            attrs.push(("diagonalUp", "1"));
+        }
+        if self.diagonal_down {
+            attrs.push(("diagonalDown", "1"));
+        }
         let writer = writer
             .create_element(tag_name)
+            .with_attributes(attrs)
             .write_inner_content::<_, XcelmateError>(|writer| {
                 self.left.write_xml(writer, "left")?;
                 self.right.write_xml(writer, "right")?;
                 self.top.write_xml(writer, "top")?;
                 self.bottom.write_xml(writer, "bottom")?;
+                self.diagonal.write_xml(writer, "diagonal")?;
                 self.vertical.write_xml(writer, "vertical")?;
                 self.horizontal.write_xml(writer, "horizontal")?;
-                self.diagonal.write_xml(writer, "diagonal")?;
                 Ok(())
             });
         Ok(writer?)
@@ -446,6 +868,12 @@ pub(crate) enum HorizontalAlignment {
     Left,
     Center,
     Right,
+    /// Repeat the cell value to fill the whole width
+    Fill,
+    Justify,
+    /// Center across selection
+    CenterContinuous,
+    Distributed,
 }
 impl ToString for HorizontalAlignment {
     fn to_string(&self) -> String {
@@ -453,6 +881,10 @@ impl ToString for HorizontalAlignment {
             HorizontalAlignment::Left => "left".into(),
             HorizontalAlignment::Center => "center".into(),
             HorizontalAlignment::Right => "right".into(),
+            HorizontalAlignment::Fill => "fill".into(),
+            HorizontalAlignment::Justify => "justify".into(),
+            HorizontalAlignment::CenterContinuous => "centerContinuous".into(),
+            HorizontalAlignment::Distributed => "distributed".into(),
         }
     }
 }
@@ -464,6 +896,8 @@ pub(crate) enum VerticalAlignment {
     Center,
     #[default]
     Bottom,
+    Justify,
+    Distributed,
 }
 impl ToString for VerticalAlignment {
     fn to_string(&self) -> String {
@@ -471,10 +905,48 @@ impl ToString for VerticalAlignment {
             VerticalAlignment::Top => "top".into(),
             VerticalAlignment::Center => "center".into(),
             VerticalAlignment::Bottom => "bottom".into(),
+            VerticalAlignment::Justify => "justify".into(),
+            VerticalAlignment::Distributed => "distributed".into(),
+        }
+    }
+}
+
+/// The direction text is read in
+#[derive(Debug, Default, PartialEq, Clone, Eq, PartialOrd, Hash, Ord)]
+pub(crate) enum ReadingOrder {
+    /// Follow the first strong character of the text
+    #[default]
+    Context,
+    LeftToRight,
+    RightToLeft,
+}
+impl From<&ReadingOrder> for u32 {
+    fn from(value: &ReadingOrder) -> Self {
+        match value {
+            ReadingOrder::Context => 0,
+            ReadingOrder::LeftToRight => 1,
+            ReadingOrder::RightToLeft => 2,
+        }
+    }
+}
+impl TryFrom<u32> for ReadingOrder {
+    type Error = XcelmateError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ReadingOrder::Context),
+            1 => Ok(ReadingOrder::LeftToRight),
+            2 => Ok(ReadingOrder::RightToLeft),
+            v => Err(XcelmateError::MissingVariant(
+                "ReadingOrder".into(),
+                v.to_string(),
+            )),
         }
     }
 }
 
+/// The text rotation that stacks letters vertically instead of turning them
+const VERTICAL_TEXT_ROTATION: u32 = 255;
 /// The alignment attributes of a cell
 #[derive(Debug, PartialEq, Default, Clone, Eq, PartialOrd, Hash, Ord)]
 pub(crate) struct Alignment {
@@ -482,6 +954,110 @@ pub(crate) struct Alignment {
     valign: VerticalAlignment,
     indent: bool,
     halign: HorizontalAlignment,
+    /// Degrees 0-90 turn text up, 91-180 turn it down by `degrees - 90` and 255 stacks it
+    text_rotation: u32,
+    /// Shrink the text until it fits the column width
+    shrink_to_fit: bool,
+    reading_order: ReadingOrder,
+    /// Distribute the last line of justified text too
+    justify_last_line: bool,
+}
+impl Alignment {
+    /// Set the text rotation, only 0-180 and 255 for vertical text are defined
+    pub(crate) fn set_text_rotation(&mut self, text_rotation: u32) -> Result<(), XcelmateError> {
+        if text_rotation > 180 && text_rotation != VERTICAL_TEXT_ROTATION {
+            return Err(XcelmateError::InvalidTextRotation(text_rotation));
+        }
+        self.text_rotation = text_rotation;
+        Ok(())
+    }
+}
+
+impl<W: Write> XmlWriter<W> for Alignment {
+    fn write_xml<'a>(
+        &self,
+        writer: &'a mut Writer<W>,
+        tag_name: &str,
+    ) -> Result<&'a mut Writer<W>, XcelmateError> {
+        let text_rotation = self.text_rotation.to_string();
+        let reading_order = u32::from(&self.reading_order).to_string();
+        let mut attrs = vec![];
+        if self.wrap {
+            attrs.push(("wrapText", "1"))
+        }
+        if self.indent {
+            attrs.push(("indent", "1"))
+        }
+        match self.valign {
+            VerticalAlignment::Top => attrs.push(("vertical", "top")),
+            VerticalAlignment::Center => attrs.push(("vertical", "center")),
+            VerticalAlignment::Justify => attrs.push(("vertical", "justify")),
+            VerticalAlignment::Distributed => attrs.push(("vertical", "distributed")),
+            VerticalAlignment::Bottom => (),
+        }
+        match self.halign {
+            HorizontalAlignment::Left => (),
+            HorizontalAlignment::Center => attrs.push(("horizontal", "center")),
+            HorizontalAlignment::Right => attrs.push(("horizontal", "right")),
+            HorizontalAlignment::Fill => attrs.push(("horizontal", "fill")),
+            HorizontalAlignment::Justify => attrs.push(("horizontal", "justify")),
+            HorizontalAlignment::CenterContinuous => attrs.push(("horizontal", "centerContinuous")),
+            HorizontalAlignment::Distributed => attrs.push(("horizontal", "distributed")),
+        }
+        if self.text_rotation != 0 {
+            attrs.push(("textRotation", text_rotation.as_str()))
+        }
+        if self.shrink_to_fit {
+            attrs.push(("shrinkToFit", "1"))
+        }
+        if self.reading_order != ReadingOrder::Context {
+            attrs.push(("readingOrder", reading_order.as_str()))
+        }
+        if self.justify_last_line {
+            attrs.push(("justifyLastLine", "1"))
+        }
+        writer
+            .create_element(tag_name)
+            .with_attributes(attrs)
+            .write_empty()?;
+        Ok(writer)
+    }
+}
+
+/// The protection of a cell, only in effect when the sheet is protected
+#[derive(Debug, PartialEq, Default, Clone, Eq, PartialOrd, Hash, Ord)]
+pub(crate) struct Protection {
+    /// Prevent the cell from being edited
+    locked: FormatState,
+    /// Hide the formula of the cell
+    hidden: FormatState,
+}
+impl Protection {
+    /// Whether this matches Excel's default of a locked cell with a visible formula
+    fn is_default(&self) -> bool {
+        self.locked != FormatState::Disabled && self.hidden != FormatState::Enabled
+    }
+}
+impl<W: Write> XmlWriter<W> for Protection {
+    fn write_xml<'a>(
+        &self,
+        writer: &'a mut Writer<W>,
+        tag_name: &str,
+    ) -> Result<&'a mut Writer<W>, XcelmateError> {
+        let mut attrs = vec![];
+        for (name, state) in [("locked", &self.locked), ("hidden", &self.hidden)] {
+            match state {
+                FormatState::Enabled => attrs.push((name, "1")),
+                FormatState::Disabled => attrs.push((name, "0")),
+                FormatState::None => (),
+            }
+        }
+        writer
+            .create_element(tag_name)
+            .with_attributes(attrs)
+            .write_empty()?;
+        Ok(writer)
+    }
 }
 
 /// The styling traits of a cell
@@ -493,13 +1069,115 @@ pub(crate) struct CellXf {
     border: Arc<Border>,
     quote_prefix: bool,
     align: Option<Alignment>,
+    protection: Option<Protection>,
+    /// The `cellStyleXfs` key of the named cell style this style is based on
+    xf_id: Option<Key>,
+    /// Allow duplicate with counter since it will always hash different
+    dup_cnt: usize,
+}
+
+/// A named cell style such as `Normal` or `Good` and the master format it applies
+#[derive(Debug, PartialEq, Default, Clone, Eq)]
+pub(crate) struct CellStyle {
+    name: String,
+    /// The `cellStyleXfs` key of the master format
+    xf_id: Key,
+    /// The id of the Excel preset this style is, if any
+    builtin_id: Option<u32>,
+    hidden: bool,
+    /// A builtin style the user has changed
+    custom_builtin: bool,
+    uid: String,
+}
+impl<W: Write> XmlWriter<W> for CellStyle {
+    fn write_xml<'a>(
+        &self,
+        writer: &'a mut Writer<W>,
+        tag_name: &str,
+    ) -> Result<&'a mut Writer<W>, XcelmateError> {
+        let xf_id = self.xf_id.to_string();
+        let builtin_id = self.builtin_id.map(|id| id.to_string());
+        let mut attrs = vec![("name", self.name.as_str()), ("xfId", xf_id.as_str())];
+        if let Some(builtin_id) = &builtin_id {
+            attrs.push(("builtinId", builtin_id));
+        }
+        if self.hidden {
+            attrs.push(("hidden", "1"));
+        }
+        if self.custom_builtin {
+            attrs.push(("customBuiltin", "1"));
+        }
+        if !self.uid.is_empty() {
+            attrs.push(("xr:uid", self.uid.as_str()));
+        }
+        writer
+            .create_element(tag_name)
+            .with_attributes(attrs)
+            .write_empty()?;
+        Ok(writer)
+    }
+}
+
+/// The `StyleBuilder` assembles a `CellXf` from its styling pieces
+#[derive(Debug, Default, Clone)]
+pub(crate) struct StyleBuilder {
+    xf: CellXf,
+}
+impl StyleBuilder {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn number_format(mut self, number_format: Arc<NumberFormat>) -> Self {
+        self.xf.number_format = Some(number_format);
+        self
+    }
+
+    pub(crate) fn font(mut self, font: Arc<FontProperty>) -> Self {
+        self.xf.font = font;
+        self
+    }
+
+    pub(crate) fn fill(mut self, fill: Arc<Fill>) -> Self {
+        self.xf.fill = fill;
+        self
+    }
+
+    pub(crate) fn border(mut self, border: Arc<Border>) -> Self {
+        self.xf.border = border;
+        self
+    }
+
+    pub(crate) fn alignment(mut self, align: Alignment) -> Self {
+        self.xf.align = Some(align);
+        self
+    }
+
+    pub(crate) fn protection(mut self, protection: Protection) -> Self {
+        self.xf.protection = Some(protection);
+        self
+    }
+
+    /// Keep text with a leading apostrophe such as `'=formula` literal
+    pub(crate) fn quote_prefix(mut self, quote_prefix: bool) -> Self {
+        self.xf.quote_prefix = quote_prefix;
+        self
+    }
+
+    pub(crate) fn build(self) -> CellXf {
+        self.xf
+    }
 }
 
 /// The styling groups for differential conditional formatting
 #[derive(Debug, PartialEq, Default, Clone, Eq, PartialOrd, Hash, Ord)]
 pub(crate) struct DiffXf {
     font: Option<FontProperty>,
+    /// Differential number formats are written inline rather than referencing `numFmts`
+    number_format: Option<NumberFormat>,
     fill: Option<Fill>,
+    align: Option<Alignment>,
+    protection: Option<Protection>,
     border: Option<Border>,
     dup_cnt: usize,
 }
@@ -515,9 +1193,18 @@ impl<W: Write> XmlWriter<W> for DiffXf {
                 if let Some(font) = &self.font {
                     font.write_xml(writer, "font")?;
                 }
+                if let Some(number_format) = &self.number_format {
+                    number_format.write_xml(writer, "numFmt")?;
+                }
                 if let Some(fill) = &self.fill {
                     fill.write_xml(writer, "fill")?;
                 }
+                if let Some(align) = &self.align {
+                    align.write_xml(writer, "alignment")?;
+                }
+                if let Some(protection) = &self.protection {
+                    protection.write_xml(writer, "protection")?;
+                }
                 if let Some(border) = &self.border {
                     border.write_xml(writer, "border")?;
                 }
@@ -532,7 +1219,7 @@ impl<W: Write> XmlWriter<W> for DiffXf {
 pub(crate) struct TableStyle {
     default_style: String,
     default_pivot_style: String,
-    styles: HashMap<String, Arc<TableCustomStyle>>,
+    styles: TableStyleMap,
 }
 
 /// Table design pieces
@@ -563,17 +1250,161 @@ pub(crate) struct TableCustomStyle {
     elements: Vec<TableStyleElement>,
 }
 
+/// The style keys that are referenced by cells and conditional formats of a workbook
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct UsedStyleKeys {
+    /// The `cellXfs` keys referenced by cells
+    pub(crate) cell_xfs: BTreeSet<Key>,
+    /// The `dxfs` keys referenced by conditional formats
+    pub(crate) diff_xfs: BTreeSet<Key>,
+}
+
+/// The keys of the styles kept by `Stylesheet::remove_unused` mapped to their new keys
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct UsedStyleKeyRemap {
+    /// The `cellXfs` remap so cells can be updated
+    pub(crate) cell_xfs: BTreeMap<Key, Key>,
+    /// The `dxfs` remap so conditional formats can be updated
+    pub(crate) diff_xfs: BTreeMap<Key, Key>,
+}
+
+/// The keys of styles merged in from another stylesheet mapped to their keys in this one
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct StyleKeyRemap {
+    /// Number formats are remapped by their id
+    pub(crate) number_formats: BTreeMap<Key, Key>,
+    pub(crate) fonts: BTreeMap<Key, Key>,
+    pub(crate) fills: BTreeMap<Key, Key>,
+    pub(crate) borders: BTreeMap<Key, Key>,
+    pub(crate) cell_style_xfs: BTreeMap<Key, Key>,
+    pub(crate) cell_xfs: BTreeMap<Key, Key>,
+    pub(crate) diff_xfs: BTreeMap<Key, Key>,
+}
+
+/// The bidirectional map backing every style table, ordered when the `deterministic` feature
+/// is enabled and hashed otherwise for faster lookups
+#[cfg(feature = "deterministic")]
+type StyleMap<T> = bimap::BiBTreeMap<Arc<T>, Key>;
+#[cfg(not(feature = "deterministic"))]
+type StyleMap<T> = bimap::BiHashMap<Arc<T>, Key>;
+
+/// The custom table styles by name, ordered under the same feature as `StyleMap`
+#[cfg(feature = "deterministic")]
+type TableStyleMap = BTreeMap<String, Arc<TableCustomStyle>>;
+#[cfg(not(feature = "deterministic"))]
+type TableStyleMap = std::collections::HashMap<String, Arc<TableCustomStyle>>;
+
+/// Iterate the entries of `table` in key order
+fn by_key<T: Ord + Hash>(table: &StyleMap<T>) -> impl Iterator<Item = &Arc<T>> {
+    (0..table.len()).filter_map(|key| table.get_by_right(&key))
+}
+
+/// The entries of `table` sorted by key
+fn entries<T: Ord + Hash>(table: &StyleMap<T>) -> Vec<(Key, Arc<T>)> {
+    let mut entries: Vec<(Key, Arc<T>)> = table.iter().map(|(v, k)| (*k, v.clone())).collect();
+    entries.sort_by_key(|(k, _)| *k);
+    entries
+}
+
+/// Get the key of an equal entry in `table` or add `item` under the next key
+fn get_or_insert<T: Ord + Hash>(table: &mut StyleMap<T>, item: Arc<T>) -> Key {
+    match table.get_by_left(&item) {
+        Some(key) => *key,
+        None => {
+            let key = table.len();
+            table.insert(item, key);
+            key
+        }
+    }
+}
+
+/// Add `xf` under the next key, counting up its duplicate count so equal entries keep their own key
+fn insert_xf(table: &mut StyleMap<CellXf>, mut xf: CellXf) {
+    while table.contains_left(&xf) {
+        xf.dup_cnt += 1;
+    }
+    table.insert(Arc::new(xf), table.len());
+}
+
+/// Find the entry of `to` that `item` from `from` was remapped to
+fn remapped<T: Ord + Hash>(
+    from: &StyleMap<T>,
+    to: &StyleMap<T>,
+    remap: &BTreeMap<Key, Key>,
+    item: &Arc<T>,
+) -> Arc<T> {
+    from.get_by_left(item)
+        .and_then(|key| remap.get(key))
+        .and_then(|key| to.get_by_right(key))
+        .unwrap_or(item)
+        .clone()
+}
+
+/// Keep only the entries of `table` accepted by `keep` and renumber them contiguously in their original order
+///
+/// # Returns
+/// The mapping from old keys to new keys of the kept entries
+fn retain_and_renumber<T: Ord + Hash>(
+    table: &mut StyleMap<T>,
+    keep: impl Fn(&Arc<T>, Key) -> bool,
+) -> BTreeMap<Key, Key> {
+    let mut entries: Vec<(Arc<T>, Key)> = table.iter().map(|(v, k)| (v.clone(), *k)).collect();
+    entries.sort_by_key(|(_, k)| *k);
+    table.clear();
+
+    let mut remap = BTreeMap::new();
+    for (item, old) in entries {
+        if keep(&item, old) {
+            let new = table.len();
+            table.insert(item, new);
+            remap.insert(old, new);
+        }
+    }
+    remap
+}
+
+/// Record an attribute of `element` that an associated reader does not understand into
+/// `warnings` when there is a sink
+fn unknown_attribute(
+    warnings: &mut Option<&mut Vec<Warning>>,
+    element: &BytesStart,
+    attr: &Attribute,
+) {
+    if let Some(warnings) = warnings {
+        warnings.push(Warning::UnknownAttribute {
+            part: STYLESHEET_PATH.into(),
+            element: String::from_utf8_lossy(element.local_name().as_ref()).into(),
+            attr: String::from_utf8_lossy(attr.key.as_ref()).into(),
+        });
+    }
+}
+
 /// The `Stylesheet` provides a mapping of styles properties such as fonts, colors, themes, etc
 #[derive(Default)]
 pub(crate) struct Stylesheet {
-    number_formats_builtin: Option<BiMap<Arc<NumberFormat>, Key>>,
-    number_formats: Option<BiMap<Arc<NumberFormat>, Key>>,
-    fonts: BiBTreeMap<Arc<FontProperty>, Key>,
-    fills: BiBTreeMap<Arc<Fill>, Key>,
-    borders: BiBTreeMap<Arc<Border>, Key>,
-    cell_xf: BiBTreeMap<Arc<CellXf>, Key>,
-    diff_xf: BiBTreeMap<Arc<DiffXf>, Key>,
+    number_formats_builtin: Option<StyleMap<NumberFormat>>,
+    number_formats: Option<StyleMap<NumberFormat>>,
+    fonts: StyleMap<FontProperty>,
+    fills: StyleMap<Fill>,
+    borders: StyleMap<Border>,
+    /// The master formats of the named cell styles
+    cell_style_xf: StyleMap<CellXf>,
+    /// The names of the master formats in `cellStyleXfs`
+    cell_styles: Vec<CellStyle>,
+    cell_xf: StyleMap<CellXf>,
+    diff_xf: StyleMap<DiffXf>,
     table_style: Option<TableStyle>,
+    /// Palette from `<indexedColors>` that overrides the default indexed colors
+    custom_palette: Option<Vec<Rgb>>,
+    /// Recently used colors from `<mruColors>`
+    mru_colors: Option<Vec<Color>>,
+    /// Write styles exactly as they were read without adding Excel's implicit defaults
+    exact_fidelity: bool,
+    /// Out of range values that were read and kept along with attributes that were dropped,
+    /// only recorded when `collect_warnings` is on
+    warnings: Vec<Warning>,
+    /// Record warnings while reading
+    collect_warnings: bool,
 }
 impl<W: Write> XmlWriter<W> for Stylesheet {
     fn write_xml<'a>(
@@ -581,11 +1412,6 @@ impl<W: Write> XmlWriter<W> for Stylesheet {
         writer: &'a mut Writer<W>,
         tag_name: &str,
     ) -> Result<&'a mut Writer<W>, XcelmateError> {
-        writer.write_event(Event::Decl(BytesDecl::new(
-            "1.0",
-            Some("UTF-8"),
-            Some("yes"),
-        )))?;
         writer
             .create_element(tag_name)
             .with_attributes(vec![
@@ -617,13 +1443,19 @@ impl<W: Write> XmlWriter<W> for Stylesheet {
             ])
             .write_inner_content::<_, XcelmateError>(|writer| {
                 // <numFmts>
-                if let Some(numfmt) = &self.number_formats {
-                    // Includes builtin in total count (should remove this)
+                let mut numfmts: Vec<_> = self
+                    .number_formats
+                    .iter()
+                    .flat_map(|n| n.iter())
+                    .filter(|(_, key)| is_custom_number_format(**key))
+                    .collect();
+                numfmts.sort_by_key(|(_, key)| **key);
+                if !numfmts.is_empty() {
                     let _ = writer
                         .create_element("numFmts")
-                        .with_attribute(("count", numfmt.len().to_string().as_str()))
+                        .with_attribute(("count", numfmts.len().to_string().as_str()))
                         .write_inner_content::<_, XcelmateError>(|writer| {
-                            for n in numfmt.left_values() {
+                            for (n, _) in numfmts {
                                 n.write_xml(writer, "numFmt")?;
                             }
                             Ok(())
@@ -634,7 +1466,7 @@ impl<W: Write> XmlWriter<W> for Stylesheet {
                     .create_element("fonts")
                     .with_attribute(("count", self.fonts.len().to_string().as_str()))
                     .write_inner_content::<_, XcelmateError>(|writer| {
-                        for (font, _) in self.fonts.right_range(0..self.fonts.len()) {
+                        for font in by_key(&self.fonts) {
                             font.write_xml(writer, "font")?;
                         }
                         Ok(())
@@ -644,8 +1476,12 @@ impl<W: Write> XmlWriter<W> for Stylesheet {
                     .create_element("fills")
                     .with_attribute(("count", self.fills.len().to_string().as_str()))
                     .write_inner_content::<_, XcelmateError>(|writer| {
-                        for (fill, _) in self.fills.right_range(0..self.fonts.len()) {
-                            fill.write_xml(writer, "fill")?;
+                        for fill in by_key(&self.fills) {
+                            if self.exact_fidelity {
+                                fill.write_xml(writer, "fill")?;
+                            } else {
+                                fill.with_default_background().write_xml(writer, "fill")?;
+                            }
                         }
                         Ok(())
                     });
@@ -654,142 +1490,84 @@ impl<W: Write> XmlWriter<W> for Stylesheet {
                     .create_element("borders")
                     .with_attribute(("count", self.borders.len().to_string().as_str()))
                     .write_inner_content::<_, XcelmateError>(|writer| {
-                        for (border, _) in self.borders.right_range(0..self.borders.len()) {
+                        for border in by_key(&self.borders) {
                             border.write_xml(writer, "border")?;
                         }
                         Ok(())
                     });
                 // <cellStyleXfs>
-                let _ = writer
-                    .create_element("cellStyleXfs")
-                    .with_attribute(("count", "1"))
-                    .write_inner_content::<_, XcelmateError>(|writer| {
-                        writer
-                            .create_element("xf")
-                            .with_attributes(vec![
-                                ("numFmtId", "0"),
-                                ("fontId", "0"),
-                                ("fillId", "0"),
-                                ("borderId", "0"),
-                            ])
-                            .write_empty()?;
-                        Ok(())
-                    });
+                let _ = if self.cell_style_xf.is_empty() {
+                    // Excel requires the master format of the `Normal` style
+                    writer
+                        .create_element("cellStyleXfs")
+                        .with_attribute(("count", "1"))
+                        .write_inner_content::<_, XcelmateError>(|writer| {
+                            writer
+                                .create_element("xf")
+                                .with_attributes(vec![
+                                    ("numFmtId", "0"),
+                                    ("fontId", "0"),
+                                    ("fillId", "0"),
+                                    ("borderId", "0"),
+                                ])
+                                .write_empty()?;
+                            Ok(())
+                        })
+                } else {
+                    writer
+                        .create_element("cellStyleXfs")
+                        .with_attribute(("count", self.cell_style_xf.len().to_string().as_str()))
+                        .write_inner_content::<_, XcelmateError>(|writer| {
+                            for xf in by_key(&self.cell_style_xf) {
+                                self.write_xf(writer, xf)?;
+                            }
+                            Ok(())
+                        })
+                };
                 // <cellXfs>
                 let _ = writer
                     .create_element("cellXfs")
                     .with_attribute(("count", self.cell_xf.len().to_string().as_str()))
                     .write_inner_content::<_, XcelmateError>(|writer| {
-                        for (xf, _) in self.cell_xf.right_range(0..self.cell_xf.len()) {
-                            let writer = writer.create_element("xf");
-
-                            let numfmt_id = if let Some(numfmt) = &xf.number_format {
-                                self.get_key_from_number_format_ref(numfmt.clone()).unwrap()
-                            } else {
-                                0
-                            };
-                            let writer = writer.with_attributes(vec![
-                                ("numFmtId", numfmt_id.to_string().as_str()),
-                                (
-                                    "fontId",
-                                    self.get_key_from_font_ref(xf.font.clone())
-                                        .unwrap()
-                                        .to_string()
-                                        .as_str(),
-                                ),
-                                (
-                                    "fillId",
-                                    self.get_key_from_fill_ref(xf.fill.clone())
-                                        .unwrap()
-                                        .to_string()
-                                        .as_str(),
-                                ),
-                                (
-                                    "borderId",
-                                    self.get_key_from_border_ref(xf.border.clone())
-                                        .unwrap()
-                                        .to_string()
-                                        .as_str(),
-                                ),
-                            ]);
-                            let writer = if xf.quote_prefix {
-                                writer.with_attribute(("quotePrefix", "1"))
-                            } else {
-                                writer
-                            };
-
-                            if let Some(align) = &xf.align {
-                                writer.write_inner_content::<_, XcelmateError>(|writer| {
-                                    let mut attrs = vec![];
-                                    if align.wrap {
-                                        attrs.push(("wrapText", "1"))
-                                    }
-                                    if align.indent {
-                                        attrs.push(("indent", "1"))
-                                    }
-                                    match align.valign {
-                                        VerticalAlignment::Top => attrs.push(("vertical", "top")),
-                                        VerticalAlignment::Center => {
-                                            attrs.push(("vertical", "center"))
-                                        }
-                                        VerticalAlignment::Bottom => (),
-                                    }
-                                    match align.halign {
-                                        HorizontalAlignment::Left => (),
-                                        HorizontalAlignment::Center => {
-                                            attrs.push(("horizontal", "center"))
-                                        }
-                                        HorizontalAlignment::Right => {
-                                            attrs.push(("horizontal", "right"))
-                                        }
-                                    }
-                                    writer
-                                        .create_element("alignment")
-                                        .with_attributes(attrs)
-                                        .write_empty()?;
-                                    Ok(())
-                                })?;
-                            } else {
-                                writer.write_empty()?;
-                            };
+                        for xf in by_key(&self.cell_xf) {
+                            self.write_xf(writer, xf)?;
                         }
                         Ok(())
                     });
                 // <cellStyles>
-                let _ = writer
-                    .create_element("cellStyles")
-                    .with_attribute(("count", "1"))
-                    .write_inner_content::<_, XcelmateError>(|writer| {
-                        writer
-                            .create_element("cellStyle")
-                            .with_attributes(vec![
-                                ("name", "Normal"),
-                                ("xfId", "0"),
-                                ("builtinId", "0"),
-                            ])
-                            .write_empty()?;
-                        Ok(())
-                    });
+                let _ = if self.cell_styles.is_empty() {
+                    writer
+                        .create_element("cellStyles")
+                        .with_attribute(("count", "1"))
+                        .write_inner_content::<_, XcelmateError>(|writer| {
+                            writer
+                                .create_element("cellStyle")
+                                .with_attributes(vec![
+                                    ("name", "Normal"),
+                                    ("xfId", "0"),
+                                    ("builtinId", "0"),
+                                ])
+                                .write_empty()?;
+                            Ok(())
+                        })
+                } else {
+                    writer
+                        .create_element("cellStyles")
+                        .with_attribute(("count", self.cell_styles.len().to_string().as_str()))
+                        .write_inner_content::<_, XcelmateError>(|writer| {
+                            for cell_style in &self.cell_styles {
+                                cell_style.write_xml(writer, "cellStyle")?;
+                            }
+                            Ok(())
+                        })
+                };
                 // <dxfs>
                 let _ = writer
                     .create_element("dxfs")
                     .with_attribute(("count", self.diff_xf.len().to_string().as_str()))
                     .write_inner_content::<_, XcelmateError>(|writer| {
-                        for (diff_xf, _) in self.diff_xf.right_range(0..self.diff_xf.len()) {
-                            let _ = writer
-                                .create_element("dxf")
-                                .write_inner_content::<_, XcelmateError>(|writer| {
-                                    if let Some(font) = &diff_xf.font {
-                                        font.write_xml(writer, "font")?;
-                                    }
-                                    if let Some(fill) = &diff_xf.fill {
-                                        fill.write_xml(writer, "fill")?;
-                                    }
-                                    if let Some(border) = &diff_xf.border {
-                                        border.write_xml(writer, "border")?;
-                                    }
-                                    Ok(())
-                                });
+                        for diff_xf in by_key(&self.diff_xf) {
+                            diff_xf.write_xml(writer, "dxf")?;
                         }
                         Ok(())
                     });
@@ -808,15 +1586,20 @@ impl<W: Write> XmlWriter<W> for Stylesheet {
                         // <tableStyle>
                         let _ =
                             table_style_writer.write_inner_content::<_, XcelmateError>(|writer| {
-                                for (_, style) in &table_style.styles {
+                                let mut styles: Vec<_> = table_style.styles.iter().collect();
+                                styles.sort_by_key(|(name, _)| *name);
+                                for (_, style) in styles {
+                                    let pivot = style.pivot.to_string();
+                                    let count = style.elements.len().to_string();
+                                    let mut attrs =
+                                        vec![("pivot", pivot.as_str()), ("count", count.as_str())];
+                                    if !style.uid.is_empty() {
+                                        attrs.push(("xr9:uid", style.uid.as_str()));
+                                    }
+                                    attrs.push(("name", style.name.as_str()));
                                     let _ = writer
                                         .create_element("tableStyle")
-                                        .with_attributes(vec![
-                                            ("pivot", style.pivot.to_string().as_str()),
-                                            ("count", style.elements.len().to_string().as_str()),
-                                            ("xr9:uid", style.uid.as_str()),
-                                            ("name", style.name.as_str()),
-                                        ])
+                                        .with_attributes(attrs)
                                         // <tableStyleElement>
                                         .write_inner_content::<_, XcelmateError>(|writer| {
                                             for ele in &style.elements {
@@ -847,28 +1630,161 @@ impl<W: Write> XmlWriter<W> for Stylesheet {
                         table_style_writer.write_empty()?;
                     }
                 }
+                // <colors>
+                if self.custom_palette.is_some() || self.mru_colors.is_some() {
+                    writer
+                        .create_element("colors")
+                        .write_inner_content::<_, XcelmateError>(|writer| {
+                            // <indexedColors>
+                            if let Some(palette) = &self.custom_palette {
+                                writer
+                                    .create_element("indexedColors")
+                                    .write_inner_content::<_, XcelmateError>(|writer| {
+                                        for rgb in palette {
+                                            writer
+                                                .create_element("rgbColor")
+                                                .with_attribute(("rgb", rgb.to_string().as_str()))
+                                                .write_empty()?;
+                                        }
+                                        Ok(())
+                                    })?;
+                            }
+                            // <mruColors>
+                            if let Some(mru_colors) = &self.mru_colors {
+                                writer
+                                    .create_element("mruColors")
+                                    .write_inner_content::<_, XcelmateError>(|writer| {
+                                        for color in mru_colors {
+                                            color.write_xml(writer, "color")?;
+                                        }
+                                        Ok(())
+                                    })?;
+                            }
+                            Ok(())
+                        })?;
+                }
                 Ok(())
             })?;
         Ok(writer)
     }
 }
-impl<W: Write + Seek, EX: FileOptionExtension> Save<W, EX> for Stylesheet {
+impl Stylesheet {
+    /// Write a single `xf` of either `cellStyleXfs` or `cellXfs`
+    fn write_xf<W: Write>(&self, writer: &mut Writer<W>, xf: &CellXf) -> Result<(), XcelmateError> {
+        let writer = writer.create_element("xf");
+
+        // Number formats are keyed by their id in both tables
+        let numfmt_id = xf.number_format.as_ref().map_or(0, |n| n.id());
+        let writer = writer.with_attributes(vec![
+            ("numFmtId", numfmt_id.to_string().as_str()),
+            (
+                "fontId",
+                self.get_key_from_font_ref(xf.font.clone())
+                    .unwrap()
+                    .to_string()
+                    .as_str(),
+            ),
+            (
+                "fillId",
+                self.get_key_from_fill_ref(xf.fill.clone())
+                    .unwrap()
+                    .to_string()
+                    .as_str(),
+            ),
+            (
+                "borderId",
+                self.get_key_from_border_ref(xf.border.clone())
+                    .unwrap()
+                    .to_string()
+                    .as_str(),
+            ),
+        ]);
+        let writer = if let Some(xf_id) = xf.xf_id {
+            writer.with_attribute(("xfId", xf_id.to_string().as_str()))
+        } else {
+            writer
+        };
+        let writer = if xf.quote_prefix {
+            writer.with_attribute(("quotePrefix", "1"))
+        } else {
+            writer
+        };
+
+        let protection = xf.protection.as_ref().filter(|p| !p.is_default());
+        if xf.align.is_some() || protection.is_some() {
+            writer.write_inner_content::<_, XcelmateError>(|writer| {
+                if let Some(align) = &xf.align {
+                    align.write_xml(writer, "alignment")?;
+                }
+                if let Some(protection) = protection {
+                    protection.write_xml(writer, "protection")?;
+                }
+                Ok(())
+            })?;
+        } else {
+            writer.write_empty()?;
+        };
+        Ok(())
+    }
+}
+impl<W: Write + Seek> Save<W> for Stylesheet {
     fn save(
         &mut self,
         writer: &mut zip::ZipWriter<W>,
-        options: FileOptions<EX>,
+        options: &SaveOptions,
     ) -> Result<(), XcelmateError> {
-        writer.start_file("xl/styles.xml", options)?;
-        self.write_xml(&mut Writer::new(writer), "styleSheet")?;
+        let mut xml = options.start_part(writer, STYLESHEET_PATH)?;
+        self.write_xml(&mut xml, "styleSheet")?;
         Ok(())
     }
 }
 impl Stylesheet {
+    /// Create a stylesheet seeded with the entries Excel requires to open a file
+    ///
+    /// Fill 0 must be `none` and fill 1 must be `gray125` since Excel reserves both, along
+    /// with a default font, an empty border and a cell style referencing them
+    pub(crate) fn with_defaults() -> Self {
+        let mut stylesheet = Stylesheet::default();
+        let font = stylesheet.add_font_ref_to_table(Arc::new(FontProperty {
+            size: "11".into(),
+            color: Some(Color::Theme { id: 1, tint: None }),
+            font: "Calibri".into(),
+            family: FontFamily::Swiss,
+            scheme: Some(FontScheme::Minor),
+            ..Default::default()
+        }));
+        let fill = stylesheet.add_fill_ref_to_table(Arc::new(Fill::default()));
+        stylesheet.add_fill_ref_to_table(Arc::new(Fill {
+            r#type: PatternFill::Gray,
+            ..Default::default()
+        }));
+        let border = stylesheet.add_border_ref_to_table(Arc::new(Border::default()));
+        let cell_style_xf = stylesheet.add_cell_style_ref_to_table(Arc::new(CellXf {
+            font: font.clone(),
+            fill: fill.clone(),
+            border: border.clone(),
+            ..Default::default()
+        }));
+        stylesheet.add_cell_ref_to_table(Arc::new(CellXf {
+            font,
+            fill,
+            border,
+            xf_id: stylesheet.get_key_from_cell_style_ref(cell_style_xf),
+            ..Default::default()
+        }));
+        stylesheet.cell_styles.push(CellStyle {
+            name: "Normal".into(),
+            builtin_id: Some(0),
+            ..Default::default()
+        });
+        stylesheet
+    }
+
     pub(crate) fn read_stylesheet<'a, RS: Read + Seek>(
         &mut self,
         zip: &'a mut ZipArchive<RS>,
     ) -> Result<(), XcelmateError> {
-        let mut xml = match xml_reader(zip, "xl/styles.xml") {
+        let mut xml = match xml_reader(zip, STYLESHEET_PATH) {
             None => return Err(XcelmateError::StylesMissing),
             Some(x) => x?,
         };
@@ -877,7 +1793,7 @@ impl Stylesheet {
             buf.clear();
             match xml.read_event_into(&mut buf) {
                 Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"numFmts" => {
-                    self.number_formats = Some(BiMap::new());
+                    self.number_formats = Some(StyleMap::new());
                 }
                 ////////////////////
                 // NUMBER FORMATS
@@ -886,20 +1802,7 @@ impl Stylesheet {
                     ////////////////////
                     // NUMBER FORMATS Attrs
                     /////////////
-                    let mut numfmt = NumberFormat::default();
-                    for attr in e.attributes() {
-                        if let Ok(a) = attr {
-                            match a.key {
-                                QName(b"numFmtId") => {
-                                    numfmt.id = a.unescape_value()?.parse::<u32>()?
-                                }
-                                QName(b"formatCode") => {
-                                    numfmt.format_code = a.unescape_value()?.to_string()
-                                }
-                                _ => (),
-                            }
-                        }
-                    }
+                    let numfmt = Stylesheet::read_number_format(e, &mut self.warning_sink())?;
                     self.add_number_format_ref_to_table(Arc::new(numfmt));
                 }
                 ////////////////////
@@ -907,7 +1810,9 @@ impl Stylesheet {
                 /////////////
                 Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"font" => {
                     // Allow duplicates by increment dup count so all duplicate reflect their respective duplicate count
-                    let mut font = Stylesheet::read_font(&mut xml, e.name())?;
+                    let mut font =
+                        Stylesheet::read_font(&mut xml, e.name(), &mut self.warning_sink())?;
+                    self.check_font(&font);
                     if let Some(id) = self.get_key_from_font_ref(font.clone().into()) {
                         let dup_cnt = self.get_font_ref_from_key(id).unwrap().dup_cnt + 1;
                         font.dup_cnt = dup_cnt;
@@ -920,150 +1825,45 @@ impl Stylesheet {
                 // FILL
                 /////////////
                 Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"fill" => {
-                    let fill = Stylesheet::read_fill(&mut xml, e.name())?;
+                    let fill = Stylesheet::read_fill(&mut xml, e.name(), &mut self.warning_sink())?;
                     self.add_fill_ref_to_table(fill.into());
                 }
                 ////////////////////
+                // COLORS
+                /////////////
+                Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"colors" => {
+                    self.read_colors(&mut xml)?;
+                }
+                ////////////////////
                 // BORDER
                 /////////////
                 Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"border" => {
-                    self.add_border_ref_to_table(
-                        Stylesheet::read_border(&mut xml, e.name())?.into(),
-                    );
+                    let border = Stylesheet::read_border(&mut xml, e, &mut self.warning_sink())?;
+                    self.add_border_ref_to_table(border.into());
                 }
                 ////////////////////
                 // CELL REFERENCES
                 /////////////
+                Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"cellStyleXfs" => {
+                    for xf in self.read_xfs(&mut xml, e.name())? {
+                        insert_xf(&mut self.cell_style_xf, xf);
+                    }
+                }
                 Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"cellXfs" => {
-                    let mut cell_xf_buf = Vec::with_capacity(1024);
-                    loop {
-                        cell_xf_buf.clear();
-                        let mut cell_xf = CellXf::default();
-                        let event = xml.read_event_into(&mut cell_xf_buf);
-                        match event {
-                            ////////////////////
-                            // CELL REFERENCES nth-1
-                            /////////////
-                            Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e))
-                                if e.local_name().as_ref() == b"xf" =>
-                            {
-                                for attr in e.attributes() {
-                                    if let Ok(a) = attr {
-                                        match a.key {
-                                            QName(b"numFmtId") => {
-                                                let key = a.unescape_value()?.parse::<usize>()?;
-                                                cell_xf.number_format =
-                                                    self.get_number_format_ref_from_key(key);
-                                            }
-                                            QName(b"fontId") => {
-                                                let key = a.unescape_value()?.parse::<usize>()?;
-                                                cell_xf.font = self.get_font_ref_from_key(key).expect("all font styles should have been captured previously");
-                                            }
-                                            QName(b"fillId") => {
-                                                let key = a.unescape_value()?.parse::<usize>()?;
-                                                cell_xf.fill = self.get_fill_ref_from_key(key).expect("all fill styles should have been captured previously");
-                                            }
-                                            QName(b"borderId") => {
-                                                let key = a.unescape_value()?.parse::<usize>()?;
-                                                cell_xf.border = self.get_border_ref_from_key(key).expect("all border styles should have been captured previously");
-                                            }
-                                            QName(b"quotePrefix") => {
-                                                let val = a.unescape_value()?.parse::<usize>()?;
-                                                if val == 1 {
-                                                    cell_xf.quote_prefix = true;
-                                                }
-                                            }
-                                            _ => (),
-                                        }
-                                    }
-                                }
-                                ////////////////////
-                                // CELL REFERENCES nth-2
-                                /////////////
-                                if let Ok(Event::Start(_)) = event {
-                                    let mut val_buf = Vec::with_capacity(1024);
-                                    loop {
-                                        val_buf.clear();
-                                        let event = xml.read_event_into(&mut val_buf);
-                                        match event {
-                                            Ok(Event::Empty(ref e))
-                                                if e.local_name().as_ref() == b"alignment" =>
-                                            {
-                                                let mut align = Alignment::default();
-                                                for attr in e.attributes() {
-                                                    if let Ok(a) = attr {
-                                                        match a.key {
-                                                            QName(b"vertical") => {
-                                                                let val =
-                                                                    a.unescape_value()?.to_string();
-                                                                match val.as_str() {
-                                                                    "center" => align.valign =
-                                                                        VerticalAlignment::Center,
-                                                                    "top" => {
-                                                                        align.valign =
-                                                                            VerticalAlignment::Top
-                                                                    }
-                                                                    _ => (),
-                                                                };
-                                                            }
-                                                            QName(b"wrapText") => {
-                                                                let val = a
-                                                                    .unescape_value()?
-                                                                    .parse::<usize>()?;
-                                                                if val == 1 {
-                                                                    align.wrap = true;
-                                                                }
-                                                            }
-                                                            QName(b"horizontal") => {
-                                                                let val =
-                                                                    a.unescape_value()?.to_string();
-                                                                match val.as_str() {
-                                                                    "center" => align.halign =
-                                                                        HorizontalAlignment::Center,
-                                                                    "right" => align.halign =
-                                                                        HorizontalAlignment::Right,
-                                                                    _ => (),
-                                                                };
-                                                            }
-                                                            QName(b"indent") => {
-                                                                let val = a
-                                                                    .unescape_value()?
-                                                                    .parse::<usize>()?;
-                                                                if val == 1 {
-                                                                    align.indent = true;
-                                                                }
-                                                            }
-                                                            _ => (),
-                                                        }
-                                                    }
-                                                }
-                                                cell_xf.align = Some(align);
-                                            }
-                                            Ok(Event::End(ref e))
-                                                if e.local_name().as_ref() == b"xf" =>
-                                            {
-                                                break
-                                            }
-                                            Ok(Event::Eof) => {
-                                                return Err(XcelmateError::XmlEof(
-                                                    "alignment".into(),
-                                                ))
-                                            }
-                                            Err(e) => return Err(XcelmateError::Xml(e)),
-                                            _ => (),
-                                        }
-                                    }
-                                }
-                                self.add_cell_ref_to_table(Arc::new(cell_xf));
-                            }
-                            Ok(Event::End(ref e)) if e.local_name().as_ref() == b"cellXfs" => break,
-                            Ok(Event::Eof) => return Err(XcelmateError::XmlEof("cellXfs".into())),
-                            Err(e) => return Err(XcelmateError::Xml(e)),
-                            _ => (),
-                        }
+                    for xf in self.read_xfs(&mut xml, e.name())? {
+                        insert_xf(&mut self.cell_xf, xf);
                     }
                 }
                 ////////////////////
+                // CELL STYLES
+                /////////////
+                Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e))
+                    if e.local_name().as_ref() == b"cellStyle" =>
+                {
+                    let cell_style = self.read_cell_style(e)?;
+                    self.cell_styles.push(cell_style);
+                }
+                ////////////////////
                 // DIFFERENTIAL REFERENCE
                 /////////////
                 Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"dxf" => {
@@ -1076,13 +1876,41 @@ impl Stylesheet {
                             // DIFFERENTIAL REFERENCE nth-1
                             /////////////
                             Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"font" => {
-                                diff_xf.font = Some(Stylesheet::read_font(&mut xml, e.name())?);
+                                let font = Stylesheet::read_font(
+                                    &mut xml,
+                                    e.name(),
+                                    &mut self.warning_sink(),
+                                )?;
+                                self.check_font(&font);
+                                diff_xf.font = Some(font);
                             }
                             Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"border" => {
-                                diff_xf.border = Some(Stylesheet::read_border(&mut xml, e.name())?);
+                                diff_xf.border = Some(Stylesheet::read_border(
+                                    &mut xml,
+                                    e,
+                                    &mut self.warning_sink(),
+                                )?);
                             }
                             Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"fill" => {
-                                diff_xf.fill = Some(Stylesheet::read_fill(&mut xml, e.name())?);
+                                diff_xf.fill = Some(Stylesheet::read_fill(
+                                    &mut xml,
+                                    e.name(),
+                                    &mut self.warning_sink(),
+                                )?);
+                            }
+                            Ok(Event::Empty(ref e)) if e.local_name().as_ref() == b"numFmt" => {
+                                diff_xf.number_format = Some(Stylesheet::read_number_format(
+                                    e,
+                                    &mut self.warning_sink(),
+                                )?);
+                            }
+                            Ok(Event::Empty(ref e)) if e.local_name().as_ref() == b"alignment" => {
+                                diff_xf.align =
+                                    Some(Stylesheet::read_alignment(e, &mut self.warning_sink())?);
+                            }
+                            Ok(Event::Empty(ref e)) if e.local_name().as_ref() == b"protection" => {
+                                diff_xf.protection =
+                                    Some(Stylesheet::read_protection(e, &mut self.warning_sink())?);
                             }
                             Ok(Event::End(ref e)) if e.local_name().as_ref() == b"dxf" => break,
                             Ok(Event::Eof) => return Err(XcelmateError::XmlEof("dxf".into())),
@@ -1118,7 +1946,7 @@ impl Stylesheet {
                                     table_style.default_pivot_style =
                                         a.unescape_value()?.to_string();
                                 }
-                                _ => (),
+                                _ => self.unknown_attribute("tableStyles", &a),
                             }
                         }
                     }
@@ -1139,7 +1967,7 @@ impl Stylesheet {
                                     table_style.default_pivot_style =
                                         a.unescape_value()?.to_string();
                                 }
-                                _ => (),
+                                _ => self.unknown_attribute("tableStyles", &a),
                             }
                         }
                     }
@@ -1166,7 +1994,7 @@ impl Stylesheet {
                                             QName(b"xr9:uid") => {
                                                 custom_style.uid = a.unescape_value()?.to_string();
                                             }
-                                            _ => (),
+                                            _ => self.unknown_attribute("tableStyle", &a),
                                         }
                                     }
                                 }
@@ -1185,7 +2013,7 @@ impl Stylesheet {
                                             QName(b"dxfId") => {
                                                 key = a.unescape_value()?.parse::<usize>()?;
                                             }
-                                            _ => (),
+                                            _ => self.unknown_attribute("tableStyleElement", &a),
                                         }
                                     }
                                 }
@@ -1232,6 +2060,259 @@ impl Stylesheet {
         Ok(())
     }
 
+    /// Remove all styles that are not referenced by `used_keys` and renumber the remaining keys.
+    /// The default entries Excel requires (first font, border, cell style and the first two fills)
+    /// are always kept.
+    ///
+    /// # Returns
+    /// The mapping from old `cellXfs` and `dxfs` keys to new keys so cells and conditional
+    /// formats can be updated
+    pub(crate) fn remove_unused(&mut self, used_keys: &UsedStyleKeys) -> UsedStyleKeyRemap {
+        let cell_xf_remap = retain_and_renumber(&mut self.cell_xf, |_, key| {
+            key == 0 || used_keys.cell_xfs.contains(&key)
+        });
+
+        // Anything a remaining cell style or named cell style points at must survive
+        let cell_xfs: Vec<Arc<CellXf>> = self
+            .cell_xf
+            .left_values()
+            .chain(self.cell_style_xf.left_values())
+            .cloned()
+            .collect();
+        retain_and_renumber(&mut self.fonts, |font, key| {
+            key == 0 || cell_xfs.iter().any(|xf| &xf.font == font)
+        });
+        retain_and_renumber(&mut self.fills, |fill, key| {
+            key <= 1 || cell_xfs.iter().any(|xf| &xf.fill == fill)
+        });
+        retain_and_renumber(&mut self.borders, |border, key| {
+            key == 0 || cell_xfs.iter().any(|xf| &xf.border == border)
+        });
+        if let Some(number_formats) = &mut self.number_formats {
+            // Number formats are keyed by their id so they are never renumbered
+            number_formats.retain(|numfmt, _| {
+                cell_xfs
+                    .iter()
+                    .any(|xf| xf.number_format.as_ref() == Some(numfmt))
+            });
+        }
+
+        let table_dxfs: Vec<Arc<DiffXf>> = self
+            .table_style
+            .iter()
+            .flat_map(|t| t.styles.values())
+            .flat_map(|style| style.elements.iter())
+            .map(|ele| match ele {
+                TableStyleElement::Table(dxf)
+                | TableStyleElement::Header(dxf)
+                | TableStyleElement::FirstRow(dxf)
+                | TableStyleElement::SecondRow(dxf) => dxf.clone(),
+            })
+            .collect();
+        let diff_xfs = retain_and_renumber(&mut self.diff_xf, |dxf, key| {
+            used_keys.diff_xfs.contains(&key) || table_dxfs.contains(dxf)
+        });
+
+        UsedStyleKeyRemap {
+            cell_xfs: cell_xf_remap,
+            diff_xfs,
+        }
+    }
+
+    /// Add the styles of `other` that this stylesheet does not already have, reusing equal ones
+    ///
+    /// Cell styles from `other` are rebuilt to point at the merged fonts, fills, borders and
+    /// number formats. Custom number formats keep their id unless it is already taken by a
+    /// different format.
+    ///
+    /// # Returns
+    /// The mapping from the keys in `other` to the keys in this stylesheet so cells and
+    /// conditional formats copied from `other` can be updated
+    pub(crate) fn merge_from(&mut self, other: &Stylesheet) -> StyleKeyRemap {
+        let mut remap = StyleKeyRemap::default();
+
+        if let Some(number_formats) = &other.number_formats {
+            for (old, numfmt) in entries(number_formats) {
+                let new = self.merge_number_format(&numfmt);
+                remap.number_formats.insert(old, new);
+            }
+        }
+        for (old, font) in entries(&other.fonts) {
+            // Duplicates only exist to keep their own key so they fold into the original
+            let font = Arc::new(FontProperty {
+                dup_cnt: 0,
+                ..(*font).clone()
+            });
+            remap
+                .fonts
+                .insert(old, get_or_insert(&mut self.fonts, font));
+        }
+        for (old, fill) in entries(&other.fills) {
+            remap
+                .fills
+                .insert(old, get_or_insert(&mut self.fills, fill));
+        }
+        for (old, border) in entries(&other.borders) {
+            remap
+                .borders
+                .insert(old, get_or_insert(&mut self.borders, border));
+        }
+        for (old, xf) in entries(&other.cell_style_xf) {
+            let xf = self.merged_xf(other, &remap, &xf);
+            remap
+                .cell_style_xfs
+                .insert(old, get_or_insert(&mut self.cell_style_xf, xf));
+        }
+        for cell_style in &other.cell_styles {
+            // Styles are looked up by name so an existing one wins
+            if self.get_cell_style_by_name(&cell_style.name).is_none() {
+                if let Some(xf_id) = remap.cell_style_xfs.get(&cell_style.xf_id) {
+                    self.cell_styles.push(CellStyle {
+                        xf_id: *xf_id,
+                        ..cell_style.clone()
+                    });
+                }
+            }
+        }
+        for (old, xf) in entries(&other.cell_xf) {
+            let xf = self.merged_xf(other, &remap, &xf);
+            remap
+                .cell_xfs
+                .insert(old, get_or_insert(&mut self.cell_xf, xf));
+        }
+        for (old, dxf) in entries(&other.diff_xf) {
+            let dxf = Arc::new(DiffXf {
+                dup_cnt: 0,
+                ..(*dxf).clone()
+            });
+            remap
+                .diff_xfs
+                .insert(old, get_or_insert(&mut self.diff_xf, dxf));
+        }
+
+        remap
+    }
+
+    /// Rebuild an `xf` of `other` to point at the merged entries of this stylesheet
+    fn merged_xf(&self, other: &Stylesheet, remap: &StyleKeyRemap, xf: &CellXf) -> Arc<CellXf> {
+        let number_format = xf.number_format.as_ref().map(|numfmt| {
+            remap
+                .number_formats
+                .get(&(numfmt.id() as Key))
+                .and_then(|key| self.number_formats.as_ref()?.get_by_right(key))
+                .unwrap_or(numfmt)
+                .clone()
+        });
+        Arc::new(CellXf {
+            number_format,
+            font: remapped(&other.fonts, &self.fonts, &remap.fonts, &xf.font),
+            fill: remapped(&other.fills, &self.fills, &remap.fills, &xf.fill),
+            border: remapped(&other.borders, &self.borders, &remap.borders, &xf.border),
+            xf_id: xf
+                .xf_id
+                .and_then(|key| remap.cell_style_xfs.get(&key).copied()),
+            dup_cnt: 0,
+            ..xf.clone()
+        })
+    }
+
+    /// Add a custom number format from another stylesheet, keeping its id when it is free
+    fn merge_number_format(&mut self, numfmt: &NumberFormat) -> Key {
+        let number_formats = self.number_formats.as_ref();
+        if let Some(key) = number_formats.and_then(|n| {
+            n.iter()
+                .find(|(item, _)| item.format_code == numfmt.format_code)
+                .map(|(_, key)| *key)
+        }) {
+            return key;
+        }
+        let id = if number_formats.is_some_and(|n| n.contains_right(&(numfmt.id() as Key))) {
+            self.next_custom_number_format_id()
+        } else {
+            numfmt.id()
+        };
+        self.add_number_format_ref_to_table(Arc::new(NumberFormat {
+            id: Some(id),
+            format_code: numfmt.format_code.clone(),
+        }))
+        .id() as Key
+    }
+
+    /// Resolve an indexed color from the workbook's custom palette, falling back to the
+    /// legacy palette
+    ///
+    /// Indices outside of the palette such as the system foreground at 64 resolve to black,
+    /// except the system background at 65 which is white
+    pub(crate) fn resolve_indexed_color(&self, idx: u32) -> Rgb {
+        let (r, g, b) = match self
+            .custom_palette
+            .as_ref()
+            .and_then(|palette| palette.get(idx as usize))
+        {
+            // Palettes are commonly written with a zero alpha that Excel ignores
+            Some(Rgb::Custom(r, g, b)) | Some(Rgb::CustomAlpha(_, r, g, b)) => (*r, *g, *b),
+            None => match idx {
+                SYSTEM_BACKGROUND_INDEX => (0xFF, 0xFF, 0xFF),
+                idx => LEGACY_PALETTE
+                    .get(idx as usize)
+                    .copied()
+                    .unwrap_or((0, 0, 0)),
+            },
+        };
+        Rgb::Custom(r, g, b)
+    }
+
+    /// The warnings recorded while reading when `set_collect_warnings` is on
+    ///
+    /// Out of range font families and the unknown attributes of every element read from the
+    /// stylesheet are recorded. Fonts of rich text runs are read by the shared strings, which
+    /// like the workbook properties do not record warnings
+    pub(crate) fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Toggle recording warnings while reading, which is off by default
+    pub(crate) fn set_collect_warnings(&mut self, collect_warnings: bool) {
+        self.collect_warnings = collect_warnings;
+    }
+
+    /// Record a warning for any font value that is preserved but not meaningful
+    fn check_font(&mut self, font: &FontProperty) {
+        if !self.collect_warnings {
+            return;
+        }
+        if let FontFamily::Other(v) = font.family {
+            self.warnings.push(Warning::InvalidValue {
+                part: STYLESHEET_PATH.into(),
+                element: "family".into(),
+                value: v.to_string(),
+            });
+        }
+    }
+
+    /// Where the associated readers record unknown attributes, which is nowhere unless
+    /// warnings are collected
+    fn warning_sink(&mut self) -> Option<&mut Vec<Warning>> {
+        self.collect_warnings.then_some(&mut self.warnings)
+    }
+
+    /// Record an attribute of `element` that the reader does not understand
+    fn unknown_attribute(&mut self, element: &str, attr: &Attribute) {
+        if self.collect_warnings {
+            self.warnings.push(Warning::UnknownAttribute {
+                part: STYLESHEET_PATH.into(),
+                element: element.into(),
+                attr: String::from_utf8_lossy(attr.key.as_ref()).into(),
+            });
+        }
+    }
+
+    /// Toggle writing styles exactly as read, which suppresses implicit defaults such as
+    /// the `<bgColor indexed="64"/>` on solid fills
+    pub(crate) fn set_exact_fidelity(&mut self, exact_fidelity: bool) {
+        self.exact_fidelity = exact_fidelity;
+    }
+
     pub(crate) fn get_custom_table_style(&self, name: &str) -> Option<Arc<TableCustomStyle>> {
         if let Some(t) = &self.table_style {
             t.styles.get(name).cloned()
@@ -1245,11 +2326,20 @@ impl Stylesheet {
         name: &str,
         style: Arc<TableCustomStyle>,
     ) -> Arc<TableCustomStyle> {
+        // Newly created styles need their own revision uid for co-authoring
+        let style = if style.uid.is_empty() {
+            Arc::new(TableCustomStyle {
+                uid: generate_uid(),
+                ..(*style).clone()
+            })
+        } else {
+            style
+        };
         if let Some(table) = &mut self.table_style {
             table.styles.insert(name.into(), style.clone());
         } else {
             self.table_style = Some(TableStyle {
-                styles: HashMap::from_iter(vec![(name.into(), style.clone())]),
+                styles: TableStyleMap::from_iter(vec![(name.into(), style.clone())]),
                 ..Default::default()
             });
         }
@@ -1260,6 +2350,29 @@ impl Stylesheet {
         self.table_style = Some(table);
     }
 
+    /// The master format of the named cell style such as `Good`
+    pub(crate) fn get_cell_style_by_name(&self, name: &str) -> Option<Arc<CellXf>> {
+        self.cell_styles
+            .iter()
+            .find(|cell_style| cell_style.name == name)
+            .and_then(|cell_style| self.get_cell_style_ref_from_key(cell_style.xf_id))
+    }
+
+    pub(crate) fn get_key_from_cell_style_ref(&self, key: Arc<CellXf>) -> Option<usize> {
+        self.cell_style_xf.get_by_left(&key).copied()
+    }
+
+    pub(crate) fn get_cell_style_ref_from_key(&self, key: Key) -> Option<Arc<CellXf>> {
+        self.cell_style_xf.get_by_right(&key).cloned()
+    }
+
+    pub(crate) fn add_cell_style_ref_to_table(&mut self, item: Arc<CellXf>) -> Arc<CellXf> {
+        let item = self.with_allocated_number_format(item);
+        self.cell_style_xf
+            .insert(item.clone(), self.cell_style_xf.len());
+        item
+    }
+
     pub(crate) fn get_key_from_cell_ref(&self, key: Arc<CellXf>) -> Option<usize> {
         if let Some(i) = self.cell_xf.get_by_left(&key) {
             Some(*i)
@@ -1277,6 +2390,7 @@ impl Stylesheet {
     }
 
     pub(crate) fn add_cell_ref_to_table(&mut self, item: Arc<CellXf>) -> Arc<CellXf> {
+        let item = self.with_allocated_number_format(item);
         self.cell_xf.insert(item.clone(), self.cell_xf.len());
         item
     }
@@ -1298,6 +2412,16 @@ impl Stylesheet {
     }
 
     pub(crate) fn add_differential_ref_to_table(&mut self, item: Arc<DiffXf>) -> Arc<DiffXf> {
+        let item = match &item.number_format {
+            Some(numfmt) if numfmt.id.is_none() => {
+                let numfmt = self.allocate_number_format(Arc::new(numfmt.clone()));
+                Arc::new(DiffXf {
+                    number_format: Some((*numfmt).clone()),
+                    ..(*item).clone()
+                })
+            }
+            _ => item,
+        };
         self.diff_xf.insert(item.clone(), self.diff_xf.len());
         item
     }
@@ -1315,7 +2439,7 @@ impl Stylesheet {
     }
 
     pub(crate) fn get_number_format_ref_from_key(&self, key: Key) -> Option<Arc<NumberFormat>> {
-        if (LOCALIZED_RANGE_NUMBER_FORMAT).contains(&key) || key > MAX_RESERVED_NUMBER_FORMAT {
+        if is_custom_number_format(key) {
             if let Some(n) = &self.number_formats {
                 if let Some(i) = n.get_by_right(&key) {
                     Some(i.clone())
@@ -1338,16 +2462,52 @@ impl Stylesheet {
         }
     }
 
+    /// Give a style's number format without an id the id of the custom format with the same
+    /// code, or the next free custom id, so it is never written as `General`
+    fn with_allocated_number_format(&mut self, item: Arc<CellXf>) -> Arc<CellXf> {
+        match &item.number_format {
+            Some(numfmt) if numfmt.id.is_none() => {
+                let numfmt = self.allocate_number_format(numfmt.clone());
+                Arc::new(CellXf {
+                    number_format: Some(numfmt),
+                    ..(*item).clone()
+                })
+            }
+            _ => item,
+        }
+    }
+
+    /// Reuse the custom number format with the same code or add this one under the next free
+    /// custom id
+    fn allocate_number_format(&mut self, numfmt: Arc<NumberFormat>) -> Arc<NumberFormat> {
+        if let Some(existing) = self.number_formats.as_ref().and_then(|n| {
+            n.iter()
+                .find(|(item, _)| item.format_code == numfmt.format_code)
+                .map(|(item, _)| item.clone())
+        }) {
+            return existing;
+        }
+        self.add_number_format_ref_to_table(numfmt)
+    }
+
     pub(crate) fn add_number_format_ref_to_table(
         &mut self,
         item: Arc<NumberFormat>,
     ) -> Arc<NumberFormat> {
-        let key = item.id as usize;
-        if (LOCALIZED_RANGE_NUMBER_FORMAT).contains(&key) || key >= MAX_RESERVED_NUMBER_FORMAT {
+        let item = if item.id.is_none() {
+            Arc::new(NumberFormat {
+                id: Some(self.next_custom_number_format_id()),
+                ..(*item).clone()
+            })
+        } else {
+            item
+        };
+        let key = item.id() as usize;
+        if is_custom_number_format(key) {
             if let Some(number_formats) = &mut self.number_formats {
                 number_formats.insert(item.clone(), key);
             } else {
-                self.number_formats = Some(BiHashMap::from_iter(vec![(item.clone(), key)]));
+                self.number_formats = Some(StyleMap::from_iter(vec![(item.clone(), key)]));
             }
 
             item
@@ -1355,7 +2515,7 @@ impl Stylesheet {
             if let Some(number_formats) = &mut self.number_formats_builtin {
                 number_formats.insert(item.clone(), key);
             } else {
-                self.number_formats = Some(BiHashMap::from_iter(vec![(item.clone(), key)]));
+                self.number_formats_builtin = Some(StyleMap::from_iter(vec![(item.clone(), key)]));
             }
 
             item
@@ -1425,15 +2585,216 @@ impl Stylesheet {
         item
     }
 
+    /// Get the key of an equal fill or add it to the table
+    pub(crate) fn get_or_insert_fill(&mut self, item: Fill) -> Key {
+        let item = Arc::new(item);
+        match self.get_key_from_fill_ref(item.clone()) {
+            Some(key) => key,
+            None => {
+                self.add_fill_ref_to_table(item);
+                self.fills.len() - 1
+            }
+        }
+    }
+
+    /// Get the key of an equal border or add it to the table
+    pub(crate) fn get_or_insert_border(&mut self, item: Border) -> Key {
+        let item = Arc::new(item);
+        match self.get_key_from_border_ref(item.clone()) {
+            Some(key) => key,
+            None => {
+                self.add_border_ref_to_table(item);
+                self.borders.len() - 1
+            }
+        }
+    }
+
+    /// Get the id of a builtin or custom number format with the same code or add it under the
+    /// next free custom id
+    pub(crate) fn get_or_insert_number_format(
+        &mut self,
+        format_code: &str,
+    ) -> Result<Key, XcelmateError> {
+        if let Some((id, _)) = BUILTIN_NUMBER_FORMATS
+            .iter()
+            .find(|(_, code)| *code == format_code)
+        {
+            let key = *id as Key;
+            if self.get_number_format_ref_from_key(key).is_none() {
+                self.add_number_format_ref_to_table(Arc::new(NumberFormat::new(*id, format_code)?));
+            }
+            return Ok(key);
+        }
+        if let Some(key) = self.number_formats.as_ref().and_then(|n| {
+            n.iter()
+                .find(|(numfmt, _)| numfmt.format_code == format_code)
+                .map(|(_, key)| *key)
+        }) {
+            return Ok(key);
+        }
+        let numfmt = NumberFormat::new(self.next_custom_number_format_id(), format_code)?;
+        Ok(self.add_number_format_ref_to_table(Arc::new(numfmt)).id() as Key)
+    }
+
+    /// The id after the highest custom number format id in use
+    fn next_custom_number_format_id(&self) -> u32 {
+        self.number_formats
+            .as_ref()
+            .and_then(|n| {
+                n.right_values()
+                    .filter(|key| **key > MAX_RESERVED_NUMBER_FORMAT)
+                    .max()
+                    .copied()
+            })
+            .unwrap_or(MAX_RESERVED_NUMBER_FORMAT) as u32
+            + 1
+    }
+
+    /// Read a color from all of its attributes, regardless of the order they appear in
+    ///
+    /// # Returns
+    /// `None` when the element carries no color attributes
+    fn read_color(
+        e: &BytesStart,
+        warnings: &mut Option<&mut Vec<Warning>>,
+    ) -> Result<Option<Color>, XcelmateError> {
+        let mut rgb = None;
+        let mut theme = None;
+        let mut tint = None;
+        let mut indexed = None;
+        let mut auto = false;
+        for attr in e.attributes() {
+            if let Ok(a) = attr {
+                match a.key {
+                    QName(b"rgb") => {
+                        rgb = Some(Stylesheet::to_rgb(a.unescape_value()?.to_string())?)
+                    }
+                    QName(b"theme") => theme = Some(a.unescape_value()?.parse::<u32>()?),
+                    QName(b"tint") => tint = Some(a.unescape_value()?.to_string()),
+                    QName(b"indexed") => indexed = Some(a.unescape_value()?.parse::<u32>()?),
+                    QName(b"auto") => auto = matches!(a.value.as_ref(), b"1" | b"true"),
+                    _ => unknown_attribute(warnings, e, &a),
+                }
+            }
+        }
+        Ok(match (rgb, theme, indexed, auto) {
+            (Some(rgb), ..) => Some(rgb),
+            (_, Some(id), ..) => Some(Color::Theme { id, tint }),
+            (_, _, Some(idx), _) => Some(Color::Index(idx)),
+            (_, _, _, true) => Some(Color::Auto),
+            _ => None,
+        })
+    }
+
+    /// Read the id and code of a number format
+    fn read_number_format(
+        e: &BytesStart,
+        warnings: &mut Option<&mut Vec<Warning>>,
+    ) -> Result<NumberFormat, XcelmateError> {
+        let mut numfmt = NumberFormat {
+            id: None,
+            format_code: String::new(),
+        };
+        for attr in e.attributes() {
+            if let Ok(a) = attr {
+                match a.key {
+                    QName(b"numFmtId") => numfmt.id = Some(a.unescape_value()?.parse::<u32>()?),
+                    QName(b"formatCode") => numfmt.format_code = a.unescape_value()?.to_string(),
+                    _ => unknown_attribute(warnings, e, &a),
+                }
+            }
+        }
+        Ok(numfmt)
+    }
+
+    /// Read the protection attributes of a cell
+    fn read_protection(
+        e: &BytesStart,
+        warnings: &mut Option<&mut Vec<Warning>>,
+    ) -> Result<Protection, XcelmateError> {
+        let mut protection = Protection::default();
+        for attr in e.attributes() {
+            if let Ok(a) = attr {
+                let state = FormatState::from(Some(matches!(a.value.as_ref(), b"1" | b"true")));
+                match a.key {
+                    QName(b"locked") => protection.locked = state,
+                    QName(b"hidden") => protection.hidden = state,
+                    _ => unknown_attribute(warnings, e, &a),
+                }
+            }
+        }
+        Ok(protection)
+    }
+
+    /// Read the alignment attributes of a cell
+    fn read_alignment(
+        e: &BytesStart,
+        warnings: &mut Option<&mut Vec<Warning>>,
+    ) -> Result<Alignment, XcelmateError> {
+        let mut align = Alignment::default();
+        for attr in e.attributes() {
+            if let Ok(a) = attr {
+                match a.key {
+                    QName(b"vertical") => match a.unescape_value()?.as_ref() {
+                        "center" => align.valign = VerticalAlignment::Center,
+                        "top" => align.valign = VerticalAlignment::Top,
+                        "justify" => align.valign = VerticalAlignment::Justify,
+                        "distributed" => align.valign = VerticalAlignment::Distributed,
+                        _ => (),
+                    },
+                    QName(b"wrapText") => {
+                        let val = a.unescape_value()?.parse::<usize>()?;
+                        if val == 1 {
+                            align.wrap = true;
+                        }
+                    }
+                    QName(b"horizontal") => match a.unescape_value()?.as_ref() {
+                        "center" => align.halign = HorizontalAlignment::Center,
+                        "right" => align.halign = HorizontalAlignment::Right,
+                        "fill" => align.halign = HorizontalAlignment::Fill,
+                        "justify" => align.halign = HorizontalAlignment::Justify,
+                        "centerContinuous" => align.halign = HorizontalAlignment::CenterContinuous,
+                        "distributed" => align.halign = HorizontalAlignment::Distributed,
+                        _ => (),
+                    },
+                    QName(b"indent") => {
+                        let val = a.unescape_value()?.parse::<usize>()?;
+                        if val == 1 {
+                            align.indent = true;
+                        }
+                    }
+                    QName(b"textRotation") => {
+                        align.set_text_rotation(a.unescape_value()?.parse::<u32>()?)?
+                    }
+                    QName(b"shrinkToFit") => {
+                        align.shrink_to_fit = matches!(a.value.as_ref(), b"1" | b"true")
+                    }
+                    QName(b"readingOrder") => {
+                        align.reading_order =
+                            ReadingOrder::try_from(a.unescape_value()?.parse::<u32>()?)?
+                    }
+                    QName(b"justifyLastLine") => {
+                        align.justify_last_line = matches!(a.value.as_ref(), b"1" | b"true")
+                    }
+                    _ => unknown_attribute(warnings, e, &a),
+                }
+            }
+        }
+        Ok(align)
+    }
+
     /// Read either left, right, top, bottom, diagonal, vertical, or horizontal of borders
     fn read_border<B: BufRead>(
         xml: &mut Reader<B>,
-        QName(mut closing): QName,
+        start: &BytesStart,
+        warnings: &mut Option<&mut Vec<Warning>>,
     ) -> Result<Border, XcelmateError> {
         fn read_region<B: BufRead>(
             xml: &mut Reader<B>,
             region: &BytesStart,
             border_region: &mut BorderRegion,
+            is_empty: bool,
+            warnings: &mut Option<&mut Vec<Warning>>,
         ) -> Result<(), XcelmateError> {
             for attr in region.attributes() {
                 if let Ok(a) = attr {
@@ -1442,36 +2803,19 @@ impl Stylesheet {
                     /////////////
                     match a.key {
                         QName(b"style") => {
-                            let val = a.unescape_value()?.to_string();
-                            match val.as_str() {
-                                "thin" => border_region.style = Some(BorderStyle::Thin),
-                                "medium" => border_region.style = Some(BorderStyle::Medium),
-                                "thick" => border_region.style = Some(BorderStyle::Thick),
-                                "double" => border_region.style = Some(BorderStyle::Double),
-                                "dashed" => border_region.style = Some(BorderStyle::Dashed),
-                                "dotted" => border_region.style = Some(BorderStyle::Dotted),
-                                "dashDot" => border_region.style = Some(BorderStyle::DashDot),
-                                "dashDotDot" => border_region.style = Some(BorderStyle::DashDotDot),
-                                "slantDashDot" => {
-                                    border_region.style = Some(BorderStyle::SlantDashDot)
-                                }
-                                "hair" => border_region.style = Some(BorderStyle::Hair),
-                                "mediumDashed" => {
-                                    border_region.style = Some(BorderStyle::MediumDashed)
-                                }
-                                "mediumDashDot" => {
-                                    border_region.style = Some(BorderStyle::MediumDashDot)
-                                }
-                                "mediumDashDotDot" => {
-                                    border_region.style = Some(BorderStyle::MediumDashDotDot)
-                                }
-                                _ => (), // Ignore unsupported or unknown values
+                            // Ignore unsupported or unknown values
+                            if let Ok(style) = BorderStyle::try_from(a.unescape_value()?.as_ref()) {
+                                border_region.style = Some(style);
                             }
                         }
-                        _ => (),
+                        _ => unknown_attribute(warnings, region, &a),
                     }
                 }
             }
+            // Self-closing regions such as `<left style="none"/>` have no color
+            if is_empty {
+                return Ok(());
+            }
             let mut border_region_buf = Vec::with_capacity(1024);
             loop {
                 border_region_buf.clear();
@@ -1480,41 +2824,7 @@ impl Stylesheet {
                     // BORDER (LRTB) nth-1
                     /////////////
                     Ok(Event::Empty(ref e)) if e.local_name().as_ref() == b"color" => {
-                        for attr in e.attributes() {
-                            if let Ok(a) = attr {
-                                match a.key {
-                                    QName(b"rgb") => {
-                                        border_region.color = Some(Stylesheet::to_rgb(
-                                            a.unescape_value()?.to_string(),
-                                        )?)
-                                    }
-                                    QName(b"theme") => {
-                                        border_region.color = Some(Color::Theme {
-                                            id: a.unescape_value()?.parse::<u32>()?,
-                                            tint: None,
-                                        });
-                                    }
-                                    QName(b"auto") => {
-                                        border_region.color =
-                                            Some(Color::Auto(a.unescape_value()?.parse::<u32>()?));
-                                    }
-                                    QName(b"indexed") => {
-                                        border_region.color =
-                                            Some(Color::Index(a.unescape_value()?.parse::<u32>()?))
-                                    }
-                                    QName(b"tint") => match border_region.color {
-                                        Some(Color::Theme { id, .. }) => {
-                                            border_region.color = Some(Color::Theme {
-                                                id,
-                                                tint: Some(a.unescape_value()?.to_string()),
-                                            })
-                                        }
-                                        _ => (),
-                                    },
-                                    _ => (),
-                                }
-                            }
-                        }
+                        border_region.color = Stylesheet::read_color(e, warnings)?;
                     }
                     Ok(Event::End(ref e)) if e.local_name().as_ref() == region.name().as_ref() => {
                         return Ok(())
@@ -1530,31 +2840,67 @@ impl Stylesheet {
             }
         }
 
-        let mut buf = Vec::with_capacity(1024);
         let mut border = Border::default();
-        loop {
+        for attr in start.attributes() {
+            if let Ok(a) = attr {
+                match a.key {
+                    QName(b"diagonalUp") => {
+                        border.diagonal_up = matches!(a.unescape_value()?.as_ref(), "1" | "true")
+                    }
+                    QName(b"diagonalDown") => {
+                        border.diagonal_down = matches!(a.unescape_value()?.as_ref(), "1" | "true")
+                    }
+                    _ => unknown_attribute(warnings, start, &a),
+                }
+            }
+        }
+
+        let QName(mut closing) = start.name();
+        let mut buf = Vec::with_capacity(1024);
+        loop {
             buf.clear();
             match xml.read_event_into(&mut buf) {
                 Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"left" => {
-                    read_region(xml, e, &mut border.left)?;
+                    read_region(xml, e, &mut border.left, false, warnings)?;
+                }
+                Ok(Event::Empty(ref e)) if e.local_name().as_ref() == b"left" => {
+                    read_region(xml, e, &mut border.left, true, warnings)?;
                 }
                 Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"right" => {
-                    read_region(xml, e, &mut border.right)?;
+                    read_region(xml, e, &mut border.right, false, warnings)?;
+                }
+                Ok(Event::Empty(ref e)) if e.local_name().as_ref() == b"right" => {
+                    read_region(xml, e, &mut border.right, true, warnings)?;
                 }
                 Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"top" => {
-                    read_region(xml, e, &mut border.top)?;
+                    read_region(xml, e, &mut border.top, false, warnings)?;
+                }
+                Ok(Event::Empty(ref e)) if e.local_name().as_ref() == b"top" => {
+                    read_region(xml, e, &mut border.top, true, warnings)?;
                 }
                 Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"bottom" => {
-                    read_region(xml, e, &mut border.bottom)?;
+                    read_region(xml, e, &mut border.bottom, false, warnings)?;
+                }
+                Ok(Event::Empty(ref e)) if e.local_name().as_ref() == b"bottom" => {
+                    read_region(xml, e, &mut border.bottom, true, warnings)?;
                 }
                 Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"vertical" => {
-                    read_region(xml, e, &mut border.vertical)?;
+                    read_region(xml, e, &mut border.vertical, false, warnings)?;
+                }
+                Ok(Event::Empty(ref e)) if e.local_name().as_ref() == b"vertical" => {
+                    read_region(xml, e, &mut border.vertical, true, warnings)?;
                 }
                 Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"diagonal" => {
-                    read_region(xml, e, &mut border.diagonal)?;
+                    read_region(xml, e, &mut border.diagonal, false, warnings)?;
+                }
+                Ok(Event::Empty(ref e)) if e.local_name().as_ref() == b"diagonal" => {
+                    read_region(xml, e, &mut border.diagonal, true, warnings)?;
                 }
                 Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"horizontal" => {
-                    read_region(xml, e, &mut border.horizontal)?;
+                    read_region(xml, e, &mut border.horizontal, false, warnings)?;
+                }
+                Ok(Event::Empty(ref e)) if e.local_name().as_ref() == b"horizontal" => {
+                    read_region(xml, e, &mut border.horizontal, true, warnings)?;
                 }
                 Ok(Event::End(ref e)) if e.local_name().as_ref() == closing => return Ok(border),
                 Ok(Event::Eof) => {
@@ -1571,6 +2917,7 @@ impl Stylesheet {
     pub(crate) fn read_font<B: BufRead>(
         xml: &mut Reader<B>,
         QName(mut closing): QName,
+        warnings: &mut Option<&mut Vec<Warning>>,
     ) -> Result<FontProperty, XcelmateError> {
         let mut buf = Vec::with_capacity(1024);
         let mut font = FontProperty::default();
@@ -1585,7 +2932,7 @@ impl Stylesheet {
                         if let Ok(a) = attr {
                             match a.key {
                                 QName(b"val") => font.size = a.unescape_value()?.to_string(),
-                                _ => (),
+                                _ => unknown_attribute(warnings, e, &a),
                             }
                         }
                     }
@@ -1601,7 +2948,7 @@ impl Stylesheet {
                                     }
                                     _ => (),
                                 },
-                                _ => (),
+                                _ => unknown_attribute(warnings, e, &a),
                             }
                         }
                     }
@@ -1617,7 +2964,7 @@ impl Stylesheet {
                                     }
                                     _ => (),
                                 },
-                                _ => (),
+                                _ => unknown_attribute(warnings, e, &a),
                             }
                         }
                     }
@@ -1633,7 +2980,7 @@ impl Stylesheet {
                                     }
                                     _ => (),
                                 },
-                                _ => (),
+                                _ => unknown_attribute(warnings, e, &a),
                             }
                         }
                     }
@@ -1650,7 +2997,7 @@ impl Stylesheet {
                                     "baseline" => font.baseline = FormatState::Enabled,
                                     _ => (),
                                 },
-                                _ => (),
+                                _ => unknown_attribute(warnings, e, &a),
                             }
                         }
                     }
@@ -1666,7 +3013,7 @@ impl Stylesheet {
                                     }
                                     _ => (),
                                 },
-                                _ => (),
+                                _ => unknown_attribute(warnings, e, &a),
                             }
                         }
                     }
@@ -1682,7 +3029,7 @@ impl Stylesheet {
                                     }
                                     _ => (),
                                 },
-                                _ => (),
+                                _ => unknown_attribute(warnings, e, &a),
                             }
                         }
                     }
@@ -1704,48 +3051,20 @@ impl Stylesheet {
                                         _ => (),
                                     }
                                 }
-                                _ => (),
+                                _ => unknown_attribute(warnings, e, &a),
                             }
                         }
                     }
                 }
                 Ok(Event::Empty(ref e)) if e.local_name().as_ref() == b"color" => {
-                    for attr in e.attributes() {
-                        if let Ok(a) = attr {
-                            match a.key {
-                                QName(b"rgb") => {
-                                    font.color =
-                                        Stylesheet::to_rgb(a.unescape_value()?.to_string())?
-                                }
-                                QName(b"theme") => {
-                                    font.color = Color::Theme {
-                                        id: a.unescape_value()?.parse::<u32>()?,
-                                        tint: None,
-                                    };
-                                }
-                                QName(b"auto") => {
-                                    font.color = Color::Auto(a.unescape_value()?.parse::<u32>()?);
-                                }
-                                QName(b"tint") => match font.color {
-                                    Color::Theme { id, .. } => {
-                                        font.color = Color::Theme {
-                                            id,
-                                            tint: Some(a.unescape_value()?.to_string()),
-                                        }
-                                    }
-                                    _ => (),
-                                },
-                                _ => (),
-                            }
-                        }
-                    }
+                    font.color = Stylesheet::read_color(e, warnings)?;
                 }
                 Ok(Event::Empty(ref e)) if e.local_name().as_ref() == b"name" => {
                     for attr in e.attributes() {
                         if let Ok(a) = attr {
                             match a.key {
                                 QName(b"val") => font.font = a.unescape_value()?.to_string(),
-                                _ => (),
+                                _ => unknown_attribute(warnings, e, &a),
                             }
                         }
                     }
@@ -1755,7 +3074,7 @@ impl Stylesheet {
                         if let Ok(a) = attr {
                             match a.key {
                                 QName(b"val") => font.font = a.unescape_value()?.to_string(),
-                                _ => (),
+                                _ => unknown_attribute(warnings, e, &a),
                             }
                         }
                     }
@@ -1765,9 +3084,9 @@ impl Stylesheet {
                         if let Ok(a) = attr {
                             match a.key {
                                 QName(b"val") => {
-                                    font.family = a.unescape_value()?.parse::<u32>()?
+                                    font.family = a.unescape_value()?.parse::<u32>()?.into()
                                 }
-                                _ => (),
+                                _ => unknown_attribute(warnings, e, &a),
                             }
                         }
                     }
@@ -1776,8 +3095,11 @@ impl Stylesheet {
                     for attr in e.attributes() {
                         if let Ok(a) = attr {
                             match a.key {
-                                QName(b"val") => font.scheme = a.unescape_value()?.to_string(),
-                                _ => (),
+                                QName(b"val") => {
+                                    font.scheme =
+                                        Some(FontScheme::try_from(a.unescape_value()?.as_ref())?)
+                                }
+                                _ => unknown_attribute(warnings, e, &a),
                             }
                         }
                     }
@@ -1794,111 +3116,222 @@ impl Stylesheet {
         }
     }
 
-    /// Read fill styling
-    fn read_fill<B: BufRead>(
+    fn read_cell_style(&mut self, e: &BytesStart) -> Result<CellStyle, XcelmateError> {
+        let mut cell_style = CellStyle::default();
+        for attr in e.attributes() {
+            if let Ok(a) = attr {
+                match a.key {
+                    QName(b"name") => cell_style.name = a.unescape_value()?.to_string(),
+                    QName(b"xfId") => cell_style.xf_id = a.unescape_value()?.parse::<usize>()?,
+                    QName(b"builtinId") => {
+                        cell_style.builtin_id = Some(a.unescape_value()?.parse::<u32>()?)
+                    }
+                    QName(b"hidden") => {
+                        cell_style.hidden = matches!(a.unescape_value()?.as_ref(), "1" | "true")
+                    }
+                    QName(b"customBuiltin") => {
+                        cell_style.custom_builtin =
+                            matches!(a.unescape_value()?.as_ref(), "1" | "true")
+                    }
+                    QName(b"xr:uid") => cell_style.uid = a.unescape_value()?.to_string(),
+                    _ => self.unknown_attribute("cellStyle", &a),
+                }
+            }
+        }
+        Ok(cell_style)
+    }
+
+    /// Read the `xf` entries of either `cellStyleXfs` or `cellXfs` in order
+    fn read_xfs<B: BufRead>(
+        &mut self,
         xml: &mut Reader<B>,
         QName(mut closing): QName,
-    ) -> Result<Fill, XcelmateError> {
-        let mut buf = Vec::with_capacity(1024);
-        let mut fill = Fill::default();
+    ) -> Result<Vec<CellXf>, XcelmateError> {
+        let mut cell_xf_buf = Vec::with_capacity(1024);
+        let mut xfs = Vec::new();
         loop {
-            buf.clear();
-            match xml.read_event_into(&mut buf) {
+            cell_xf_buf.clear();
+            let mut cell_xf = CellXf::default();
+            let event = xml.read_event_into(&mut cell_xf_buf);
+            match event {
                 ////////////////////
-                // FILL nth-1
+                // CELL REFERENCES nth-1
                 /////////////
                 Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e))
-                    if e.local_name().as_ref() == b"patternFill" =>
+                    if e.local_name().as_ref() == b"xf" =>
                 {
                     for attr in e.attributes() {
                         if let Ok(a) = attr {
                             match a.key {
-                                QName(b"patternType") => {
-                                    let val = a.unescape_value()?.to_string();
-                                    match val.as_str() {
-                                        "solid" => fill.r#type = PatternFill::Solid,
-                                        "none" => fill.r#type = PatternFill::None,
-                                        "gray125" => fill.r#type = PatternFill::Gray,
-                                        _ => (),
+                                QName(b"numFmtId") => {
+                                    let key = a.unescape_value()?.parse::<usize>()?;
+                                    cell_xf.number_format =
+                                        self.get_number_format_ref_from_key(key);
+                                    // Builtin formats are only referenced, never declared
+                                    if cell_xf.number_format.is_none()
+                                        && key != 0
+                                        && !is_custom_number_format(key)
+                                    {
+                                        cell_xf.number_format =
+                                            Some(self.add_number_format_ref_to_table(Arc::new(
+                                                NumberFormat {
+                                                    id: Some(key as u32),
+                                                    format_code: String::new(),
+                                                },
+                                            )));
                                     }
                                 }
-                                _ => (),
+                                QName(b"fontId") => {
+                                    let key = a.unescape_value()?.parse::<usize>()?;
+                                    cell_xf.font = self.get_font_ref_from_key(key).expect(
+                                        "all font styles should have been captured previously",
+                                    );
+                                }
+                                QName(b"fillId") => {
+                                    let key = a.unescape_value()?.parse::<usize>()?;
+                                    cell_xf.fill = self.get_fill_ref_from_key(key).expect(
+                                        "all fill styles should have been captured previously",
+                                    );
+                                }
+                                QName(b"borderId") => {
+                                    let key = a.unescape_value()?.parse::<usize>()?;
+                                    cell_xf.border = self.get_border_ref_from_key(key).expect(
+                                        "all border styles should have been captured previously",
+                                    );
+                                }
+                                QName(b"xfId") => {
+                                    cell_xf.xf_id = Some(a.unescape_value()?.parse::<usize>()?);
+                                }
+                                QName(b"quotePrefix") => {
+                                    let val = a.unescape_value()?.parse::<usize>()?;
+                                    if val == 1 {
+                                        cell_xf.quote_prefix = true;
+                                    }
+                                }
+                                _ => self.unknown_attribute("xf", &a),
                             }
                         }
                     }
-                }
-                Ok(Event::Empty(ref e)) if e.local_name().as_ref() == b"fgColor" => {
-                    for attr in e.attributes() {
-                        if let Ok(a) = attr {
-                            match a.key {
-                                QName(b"rgb") => {
-                                    fill.foreground =
-                                        Some(Stylesheet::to_rgb(a.unescape_value()?.to_string())?)
-                                }
-                                QName(b"theme") => {
-                                    fill.foreground = Some(Color::Theme {
-                                        id: a.unescape_value()?.parse::<u32>()?,
-                                        tint: None,
-                                    });
+                    ////////////////////
+                    // CELL REFERENCES nth-2
+                    /////////////
+                    if let Ok(Event::Start(_)) = event {
+                        let mut val_buf = Vec::with_capacity(1024);
+                        loop {
+                            val_buf.clear();
+                            let event = xml.read_event_into(&mut val_buf);
+                            match event {
+                                Ok(Event::Empty(ref e))
+                                    if e.local_name().as_ref() == b"alignment" =>
+                                {
+                                    cell_xf.align = Some(Stylesheet::read_alignment(
+                                        e,
+                                        &mut self.warning_sink(),
+                                    )?);
                                 }
-                                QName(b"auto") => {
-                                    fill.foreground =
-                                        Some(Color::Auto(a.unescape_value()?.parse::<u32>()?));
+                                Ok(Event::Empty(ref e))
+                                    if e.local_name().as_ref() == b"protection" =>
+                                {
+                                    cell_xf.protection = Some(Stylesheet::read_protection(
+                                        e,
+                                        &mut self.warning_sink(),
+                                    )?);
                                 }
-                                QName(b"indexed") => {
-                                    fill.foreground =
-                                        Some(Color::Index(a.unescape_value()?.parse::<u32>()?))
+                                Ok(Event::End(ref e)) if e.local_name().as_ref() == b"xf" => break,
+                                Ok(Event::Eof) => {
+                                    return Err(XcelmateError::XmlEof("alignment".into()))
                                 }
-                                QName(b"tint") => match fill.foreground {
-                                    Some(Color::Theme { id, .. }) => {
-                                        fill.foreground = Some(Color::Theme {
-                                            id,
-                                            tint: Some(a.unescape_value()?.to_string()),
-                                        })
-                                    }
-                                    _ => (),
-                                },
+                                Err(e) => return Err(XcelmateError::Xml(e)),
                                 _ => (),
                             }
                         }
                     }
+                    xfs.push(cell_xf);
                 }
-                Ok(Event::Empty(ref e)) if e.local_name().as_ref() == b"bgColor" => {
+                Ok(Event::End(ref e)) if e.local_name().as_ref() == closing => return Ok(xfs),
+                Ok(Event::Eof) => {
+                    let mut name = String::new();
+                    let _ = closing.read_to_string(&mut name)?;
+                    return Err(XcelmateError::XmlEof(name));
+                }
+                Err(e) => return Err(XcelmateError::Xml(e)),
+                _ => (),
+            }
+        }
+    }
+
+    /// Read the custom indexed palette and recently used colors
+    fn read_colors<B: BufRead>(&mut self, xml: &mut Reader<B>) -> Result<(), XcelmateError> {
+        let mut buf = Vec::with_capacity(1024);
+        loop {
+            buf.clear();
+            match xml.read_event_into(&mut buf) {
+                Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"indexedColors" => {
+                    self.custom_palette = Some(Vec::new());
+                }
+                Ok(Event::Empty(ref e)) if e.local_name().as_ref() == b"rgbColor" => {
+                    let color = Stylesheet::read_color(e, &mut self.warning_sink())?;
+                    if let (Some(Color::Rgb(rgb)), Some(palette)) =
+                        (color, self.custom_palette.as_mut())
+                    {
+                        palette.push(rgb);
+                    }
+                }
+                Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"mruColors" => {
+                    self.mru_colors = Some(Vec::new());
+                }
+                Ok(Event::Empty(ref e)) if e.local_name().as_ref() == b"color" => {
+                    let color = Stylesheet::read_color(e, &mut self.warning_sink())?;
+                    if let (Some(color), Some(mru_colors)) = (color, self.mru_colors.as_mut()) {
+                        mru_colors.push(color);
+                    }
+                }
+                Ok(Event::End(ref e)) if e.local_name().as_ref() == b"colors" => return Ok(()),
+                Ok(Event::Eof) => return Err(XcelmateError::XmlEof("colors".into())),
+                Err(e) => return Err(XcelmateError::Xml(e)),
+                _ => (),
+            }
+        }
+    }
+
+    /// Read fill styling
+    fn read_fill<B: BufRead>(
+        xml: &mut Reader<B>,
+        QName(mut closing): QName,
+        warnings: &mut Option<&mut Vec<Warning>>,
+    ) -> Result<Fill, XcelmateError> {
+        let mut buf = Vec::with_capacity(1024);
+        let mut fill = Fill::default();
+        loop {
+            buf.clear();
+            match xml.read_event_into(&mut buf) {
+                ////////////////////
+                // FILL nth-1
+                /////////////
+                Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e))
+                    if e.local_name().as_ref() == b"patternFill" =>
+                {
                     for attr in e.attributes() {
                         if let Ok(a) = attr {
                             match a.key {
-                                QName(b"rgb") => {
-                                    fill.background =
-                                        Some(Stylesheet::to_rgb(a.unescape_value()?.to_string())?);
-                                }
-                                QName(b"theme") => {
-                                    fill.background = Some(Color::Theme {
-                                        id: a.unescape_value()?.to_string().parse::<u32>()?,
-                                        tint: None,
-                                    });
-                                }
-                                QName(b"auto") => {
-                                    fill.background =
-                                        Some(Color::Auto(a.unescape_value()?.parse::<u32>()?));
-                                }
-                                QName(b"indexed") => {
-                                    fill.background =
-                                        Some(Color::Index(a.unescape_value()?.parse::<u32>()?))
-                                }
-                                QName(b"tint") => match fill.background {
-                                    Some(Color::Theme { id, .. }) => {
-                                        fill.background = Some(Color::Theme {
-                                            id,
-                                            tint: Some(a.unescape_value()?.to_string()),
-                                        })
+                                QName(b"patternType") => {
+                                    if let Ok(r#type) =
+                                        PatternFill::try_from(a.unescape_value()?.as_ref())
+                                    {
+                                        fill.r#type = r#type;
                                     }
-                                    _ => (),
-                                },
-                                _ => (),
+                                }
+                                _ => unknown_attribute(warnings, e, &a),
                             }
                         }
                     }
                 }
+                Ok(Event::Empty(ref e)) if e.local_name().as_ref() == b"fgColor" => {
+                    fill.foreground = Stylesheet::read_color(e, warnings)?;
+                }
+                Ok(Event::Empty(ref e)) if e.local_name().as_ref() == b"bgColor" => {
+                    fill.background = Stylesheet::read_color(e, warnings)?;
+                }
                 Ok(Event::End(ref e)) if e.local_name().as_ref() == closing => return Ok(fill),
                 Ok(Event::Eof) => {
                     let mut name = String::new();
@@ -1912,13 +3345,20 @@ impl Stylesheet {
     }
 
     /// Convert from hexadecimal to a tuple of RGB model
+    ///
+    /// The first two letters are the alpha channel, which is only kept when the color is not
+    /// fully opaque
     pub(crate) fn to_rgb(value: String) -> Result<Color, XcelmateError> {
-        // The first two letter are ignored since they response to alpha
         let base16 = 16u32;
+        let alpha = u8::from_str_radix(&value[0..2], base16)?;
         let red = u8::from_str_radix(&value[2..4], base16)?;
         let green = u8::from_str_radix(&value[4..6], base16)?;
         let blue = u8::from_str_radix(&value[6..8], base16)?;
-        Ok(Color::Rgb(Rgb::Custom(red, green, blue)))
+        if alpha == 0xFF {
+            Ok(Color::Rgb(Rgb::Custom(red, green, blue)))
+        } else {
+            Ok(Color::Rgb(Rgb::CustomAlpha(alpha, red, green, blue)))
+        }
     }
 
     /// Convert from u8 to a hexadecimal of RGB model scale
@@ -1930,8 +3370,13 @@ impl Stylesheet {
 #[cfg(test)]
 mod stylesheet_unittests {
     use super::Stylesheet;
-    use std::fs::File;
-    use zip::ZipArchive;
+    use crate::stream::utils::XmlWriter;
+    use quick_xml::Writer;
+    use std::{
+        fs::File,
+        io::{Cursor, Write},
+    };
+    use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
 
     fn init(path: &str) -> Stylesheet {
         let file = File::open(path).unwrap();
@@ -1941,23 +3386,59 @@ mod stylesheet_unittests {
         stylesheet
     }
 
+    /// Read a stylesheet from a minimal styles.xml that holds a default font, fill and border
+    /// along with any extra `sections`
+    fn init_from_xml(sections: &str) -> Stylesheet {
+        let styles = format!(
+            r#"<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><fonts count="1"><font><sz val="11"/><color theme="1"/><name val="Calibri"/><family val="2"/><scheme val="minor"/></font></fonts><fills count="1"><fill><patternFill patternType="none"/></fill></fills><borders count="1"><border><left/><right/><top/><bottom/><diagonal/></border></borders>{sections}</styleSheet>"#
+        );
+        init_from_bytes(styles.as_bytes())
+    }
+
+    /// Read a stylesheet from the raw bytes of a styles.xml
+    fn init_from_bytes(styles: &[u8]) -> Stylesheet {
+        read_into(Stylesheet::default(), styles)
+    }
+
+    /// Read the raw bytes of a styles.xml into an already configured stylesheet
+    fn read_into(mut stylesheet: Stylesheet, styles: &[u8]) -> Stylesheet {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("xl/styles.xml", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(styles).unwrap();
+        let mut zip = ZipArchive::new(zip.finish().unwrap()).unwrap();
+        stylesheet.read_stylesheet(&mut zip).unwrap();
+        stylesheet
+    }
+
+    /// Write the stylesheet into the raw bytes of a styles.xml
+    fn write(stylesheet: &Stylesheet) -> Vec<u8> {
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        stylesheet.write_xml(&mut writer, "styleSheet").unwrap();
+        writer.into_inner().into_inner()
+    }
+
     mod stylesheet_api {
-        use super::init;
-        use crate::stream::utils::Save;
+        use super::{init, init_from_bytes, init_from_xml, read_into, write};
+        use crate::errors::{Warning, XcelmateError};
+        use crate::stream::utils::{assert_xml_eq, Save, SaveOptions, XmlDeclaration, XmlWriter};
         use crate::stream::xlsx::stylesheet::{
-            Border, BorderRegion, BorderStyle, CellXf, DiffXf, Fill, FontProperty, FormatState,
-            NumberFormat, PatternFill,
+            Alignment, Border, BorderRegion, BorderStyle, CellStyle, CellXf, DiffXf, Fill,
+            FontFamily, FontProperty, FontScheme, FormatState, HorizontalAlignment, NumberFormat,
+            PatternFill, Protection, ReadingOrder, StyleBuilder, TableCustomStyle, UsedStyleKeys,
+            VerticalAlignment, BORDER_STYLES, PATTERN_FILLS,
         };
         use crate::stream::xlsx::{
             stylesheet::{Color, Rgb},
+            theme::Theme,
             Stylesheet,
         };
-        use quick_xml::{events::Event, Reader};
+        use quick_xml::{events::Event, Reader, Writer};
+        use std::collections::BTreeMap;
         use std::fs::File;
-        use std::io::Cursor;
+        use std::io::{Cursor, Read};
         use std::sync::Arc;
-        use zip::write::SimpleFileOptions;
-        use zip::{CompressionMethod, ZipWriter};
+        use zip::{ZipArchive, ZipWriter};
 
         #[test]
         fn get_custom_table_style() {
@@ -1972,6 +3453,23 @@ mod stylesheet_unittests {
             assert_eq!(result, Color::Rgb(Rgb::Custom(87, 51, 69)));
         }
 
+        #[test]
+        fn test_to_rgb_with_alpha() {
+            let result = Stylesheet::to_rgb("80FF0000".into()).unwrap();
+            assert_eq!(result, Color::Rgb(Rgb::CustomAlpha(0x80, 0xFF, 0, 0)));
+            assert_eq!(Rgb::CustomAlpha(0x80, 0xFF, 0, 0).to_string(), "80FF0000");
+            assert_eq!(Rgb::Custom(0xFF, 0, 0).to_string(), "FFFF0000");
+
+            // The alpha is written back as read
+            let style = init_from_xml(
+                r#"<cellXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellXfs><colors><indexedColors><rgbColor rgb="00000000"/></indexedColors><mruColors><color rgb="80FF0000"/></mruColors></colors>"#,
+            );
+            let actual = String::from_utf8(write(&style)).unwrap();
+            assert!(actual.contains(r#"<color rgb="80FF0000"/>"#));
+            assert!(actual.contains(r#"<rgbColor rgb="00000000"/>"#));
+            assert_eq!(style.resolve_indexed_color(0), Rgb::Custom(0, 0, 0));
+        }
+
         #[test]
         fn test_read_border_region_for_empty_borders() {
             let xml_content = r#"
@@ -1990,7 +3488,7 @@ mod stylesheet_unittests {
             loop {
                 match xml.read_event_into(&mut buf) {
                     Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"border" => {
-                        let border = Stylesheet::read_border(&mut xml, e.name()).unwrap();
+                        let border = Stylesheet::read_border(&mut xml, e, &mut None).unwrap();
 
                         assert_eq!(border.left, BorderRegion::default());
 
@@ -2041,7 +3539,7 @@ mod stylesheet_unittests {
             loop {
                 match xml.read_event_into(&mut buf) {
                     Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"border" => {
-                        let border = Stylesheet::read_border(&mut xml, e.name()).unwrap();
+                        let border = Stylesheet::read_border(&mut xml, e, &mut None).unwrap();
                         assert_eq!(
                             border.left,
                             BorderRegion {
@@ -2127,7 +3625,7 @@ mod stylesheet_unittests {
             loop {
                 match xml.read_event_into(&mut buf) {
                     Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"border" => {
-                        let actual = Stylesheet::read_border(&mut xml, e.name())
+                        let actual = Stylesheet::read_border(&mut xml, e, &mut None)
                             .err()
                             .unwrap()
                             .to_string();
@@ -2152,7 +3650,7 @@ mod stylesheet_unittests {
             loop {
                 match xml.read_event_into(&mut buf) {
                     Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"border" => {
-                        let actual = Stylesheet::read_border(&mut xml, e.name())
+                        let actual = Stylesheet::read_border(&mut xml, e, &mut None)
                             .err()
                             .unwrap()
                             .to_string();
@@ -2181,7 +3679,7 @@ mod stylesheet_unittests {
             loop {
                 match xml.read_event_into(&mut buf) {
                     Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"font" => {
-                        let actual = Stylesheet::read_font(&mut xml, e.name())
+                        let actual = Stylesheet::read_font(&mut xml, e.name(), &mut None)
                             .err()
                             .unwrap()
                             .to_string();
@@ -2206,7 +3704,7 @@ mod stylesheet_unittests {
             loop {
                 match xml.read_event_into(&mut buf) {
                     Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"font" => {
-                        let actual = Stylesheet::read_font(&mut xml, e.name())
+                        let actual = Stylesheet::read_font(&mut xml, e.name(), &mut None)
                             .err()
                             .unwrap()
                             .to_string();
@@ -2242,7 +3740,7 @@ mod stylesheet_unittests {
             loop {
                 match xml.read_event_into(&mut buf) {
                     Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"font" => {
-                        let actual = Stylesheet::read_font(&mut xml, e.name()).unwrap();
+                        let actual = Stylesheet::read_font(&mut xml, e.name(), &mut None).unwrap();
                         assert_eq!(
                             actual,
                             FontProperty {
@@ -2250,10 +3748,10 @@ mod stylesheet_unittests {
                                 double: FormatState::Enabled,
                                 italic: FormatState::Enabled,
                                 size: "21".into(),
-                                color: Color::Theme { id: 1, tint: None },
+                                color: Some(Color::Theme { id: 1, tint: None }),
                                 font: "Calibri".into(),
-                                family: 2,
-                                scheme: "minor".into(),
+                                family: FontFamily::Swiss,
+                                scheme: Some(FontScheme::Minor),
                                 ..Default::default()
                             }
                         );
@@ -2289,7 +3787,7 @@ mod stylesheet_unittests {
             loop {
                 match xml.read_event_into(&mut buf) {
                     Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"font" => {
-                        let actual = Stylesheet::read_font(&mut xml, e.name()).unwrap();
+                        let actual = Stylesheet::read_font(&mut xml, e.name(), &mut None).unwrap();
                         assert_eq!(
                             actual,
                             FontProperty {
@@ -2297,10 +3795,10 @@ mod stylesheet_unittests {
                                 italic: FormatState::Enabled,
                                 underline: FormatState::Disabled,
                                 size: "21".into(),
-                                color: Color::Theme { id: 1, tint: None },
+                                color: Some(Color::Theme { id: 1, tint: None }),
                                 font: "Calibri".into(),
-                                family: 2,
-                                scheme: "minor".into(),
+                                family: FontFamily::Swiss,
+                                scheme: Some(FontScheme::Minor),
                                 ..Default::default()
                             }
                         );
@@ -2336,7 +3834,7 @@ mod stylesheet_unittests {
             loop {
                 match xml.read_event_into(&mut buf) {
                     Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"font" => {
-                        let actual = Stylesheet::read_font(&mut xml, e.name()).unwrap();
+                        let actual = Stylesheet::read_font(&mut xml, e.name(), &mut None).unwrap();
                         assert_eq!(
                             actual,
                             FontProperty {
@@ -2344,10 +3842,10 @@ mod stylesheet_unittests {
                                 underline: FormatState::Enabled,
                                 italic: FormatState::Enabled,
                                 size: "21".into(),
-                                color: Color::Theme { id: 1, tint: None },
+                                color: Some(Color::Theme { id: 1, tint: None }),
                                 font: "Calibri".into(),
-                                family: 2,
-                                scheme: "minor".into(),
+                                family: FontFamily::Swiss,
+                                scheme: Some(FontScheme::Minor),
                                 ..Default::default()
                             }
                         );
@@ -2376,7 +3874,7 @@ mod stylesheet_unittests {
             loop {
                 match xml.read_event_into(&mut buf) {
                     Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"fills" => {
-                        let actual = Stylesheet::read_fill(&mut xml, e.name())
+                        let actual = Stylesheet::read_fill(&mut xml, e.name(), &mut None)
                             .err()
                             .unwrap()
                             .to_string();
@@ -2401,7 +3899,7 @@ mod stylesheet_unittests {
             loop {
                 match xml.read_event_into(&mut buf) {
                     Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"fills" => {
-                        let actual = Stylesheet::read_fill(&mut xml, e.name())
+                        let actual = Stylesheet::read_fill(&mut xml, e.name(), &mut None)
                             .err()
                             .unwrap()
                             .to_string();
@@ -2430,7 +3928,7 @@ mod stylesheet_unittests {
             loop {
                 match xml.read_event_into(&mut buf) {
                     Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"fill" => {
-                        let actual = Stylesheet::read_fill(&mut xml, e.name()).unwrap();
+                        let actual = Stylesheet::read_fill(&mut xml, e.name(), &mut None).unwrap();
                         assert_eq!(
                             actual,
                             Fill {
@@ -2464,7 +3962,7 @@ mod stylesheet_unittests {
             loop {
                 match xml.read_event_into(&mut buf) {
                     Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"fill" => {
-                        let actual = Stylesheet::read_fill(&mut xml, e.name()).unwrap();
+                        let actual = Stylesheet::read_fill(&mut xml, e.name(), &mut None).unwrap();
                         assert_eq!(
                             actual,
                             Fill {
@@ -2501,7 +3999,7 @@ mod stylesheet_unittests {
             loop {
                 match xml.read_event_into(&mut buf) {
                     Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"fill" => {
-                        let actual = Stylesheet::read_fill(&mut xml, e.name()).unwrap();
+                        let actual = Stylesheet::read_fill(&mut xml, e.name(), &mut None).unwrap();
                         assert_eq!(
                             actual,
                             Fill {
@@ -2537,10 +4035,10 @@ mod stylesheet_unittests {
                     number_format: None,
                     font: Arc::new(FontProperty {
                         size: "11".into(),
-                        color: Color::Rgb(Rgb::Custom(156, 0, 6,)),
+                        color: Some(Color::Rgb(Rgb::Custom(156, 0, 6,))),
                         font: "Calibri".into(),
-                        family: 2,
-                        scheme: "minor".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Minor),
                         ..Default::default()
                     }),
                     fill: Arc::new(Fill {
@@ -2551,10 +4049,36 @@ mod stylesheet_unittests {
                     border: Arc::new(Border {
                         ..Default::default()
                     }),
+                    xf_id: Some(12),
                     ..Default::default()
                 }
                 .into()
             );
+
+            // A cell with a hidden formula
+            let style = init_from_xml(
+                r#"<cellXfs count="2"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/><xf numFmtId="0" fontId="0" fillId="0" borderId="0" applyProtection="1"><protection hidden="1"/></xf></cellXfs>"#,
+            );
+            let actual = style.get_cell_ref_from_key(1).unwrap();
+            assert_eq!(
+                actual.protection,
+                Some(Protection {
+                    locked: FormatState::None,
+                    hidden: FormatState::Enabled,
+                })
+            );
+            let written = String::from_utf8(write(&style)).unwrap();
+            assert!(written.contains(r#"<protection hidden="1"/>"#));
+        }
+
+        #[test]
+        fn test_default_protection_is_not_written() {
+            let style = init_from_xml(
+                r#"<cellXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"><protection locked="1" hidden="0"/></xf></cellXfs>"#,
+            );
+            assert!(style.get_cell_ref_from_key(0).unwrap().protection.is_some());
+            let written = String::from_utf8(write(&style)).unwrap();
+            assert!(!written.contains("<protection"));
         }
 
         #[test]
@@ -2573,10 +4097,10 @@ mod stylesheet_unittests {
                         baseline: FormatState::Enabled,
                         underline: FormatState::Disabled,
                         size: "11".into(),
-                        color: Color::Theme { id: 0, tint: None },
+                        color: Some(Color::Theme { id: 0, tint: None }),
                         font: "Posterama".into(),
-                        family: 2,
-                        scheme: "major".into(),
+                        family: FontFamily::Swiss,
+                        scheme: Some(FontScheme::Major),
                         ..Default::default()
                     }),
                     dup_cnt: 1, // verifies duplicates allowed
@@ -2605,12 +4129,38 @@ mod stylesheet_unittests {
             assert_eq!(
                 actual,
                 Some(Arc::new(NumberFormat {
-                    id: 43,
+                    id: Some(43),
                     format_code: r#"_(* #,##0.00_);_(* \(#,##0.00\);_(* "-"??_);_(@_)"#.into()
                 }))
             )
         }
 
+        #[test]
+        fn test_write_only_custom_number_formats() {
+            let style = init_from_xml(
+                r#"<numFmts count="2"><numFmt numFmtId="14" formatCode="m/d/yyyy"/><numFmt numFmtId="164" formatCode="0.0%"/></numFmts><cellXfs count="3"><xf numFmtId="14" fontId="0" fillId="0" borderId="0"/><xf numFmtId="164" fontId="0" fillId="0" borderId="0"/><xf numFmtId="9" fontId="0" fillId="0" borderId="0"/></cellXfs>"#,
+            );
+            assert_eq!(style.get_number_format_ref_from_key(14).unwrap().id(), 14);
+            assert_eq!(style.get_number_format_ref_from_key(164).unwrap().id(), 164);
+
+            let actual = String::from_utf8(write(&style)).unwrap();
+            assert!(actual.contains(
+                r#"<numFmts count="1"><numFmt numFmtId="164" formatCode="0.0%"/></numFmts>"#
+            ));
+            // Builtin formats are still referenced by their id
+            assert!(actual.contains(r#"<xf numFmtId="14" "#));
+            assert!(actual.contains(r#"<xf numFmtId="164" "#));
+            assert!(actual.contains(r#"<xf numFmtId="9" "#));
+
+            // Files without custom formats have no numFmts at all
+            let style = init_from_xml(
+                r#"<cellXfs count="1"><xf numFmtId="14" fontId="0" fillId="0" borderId="0"/></cellXfs>"#,
+            );
+            let actual = String::from_utf8(write(&style)).unwrap();
+            assert!(!actual.contains("<numFmts"));
+            assert!(actual.contains(r#"<xf numFmtId="14" "#));
+        }
+
         #[test]
         fn test_get_number_format_ref_from_key_and_not_exists() {
             let style = init("tests/workbook04.xlsx");
@@ -2630,10 +4180,10 @@ mod stylesheet_unittests {
                 actual,
                 Some(Arc::new(FontProperty {
                     size: "18".into(),
-                    color: Color::Theme { id: 3, tint: None },
+                    color: Some(Color::Theme { id: 3, tint: None }),
                     font: "Calibri Light".into(),
-                    family: 2,
-                    scheme: "major".into(),
+                    family: FontFamily::Swiss,
+                    scheme: Some(FontScheme::Major),
                     ..Default::default()
                 }))
             )
@@ -2705,16 +4255,1048 @@ mod stylesheet_unittests {
         fn save_file() {
             let mut style = init("tests/workbook04.xlsx");
             let mut zip = ZipWriter::new(Cursor::new(Vec::<u8>::new()));
-            style
-                .save(
-                    &mut zip,
-                    SimpleFileOptions::default().compression_method(CompressionMethod::Deflated),
-                )
-                .unwrap();
+            style.save(&mut zip, &SaveOptions::default()).unwrap();
 
             // Verify all data is written
             assert!(zip.finish().unwrap().into_inner().len() > 22);
         }
 
+        #[test]
+        fn test_border_style_round_trip() {
+            for (style, _) in BORDER_STYLES {
+                let border = Border {
+                    left: BorderRegion {
+                        style: Some(style.clone()),
+                        color: Some(Color::Rgb(Rgb::Custom(35, 69, 103))),
+                    },
+                    right: BorderRegion {
+                        style: Some(style.clone()),
+                        color: None,
+                    },
+                    ..Default::default()
+                };
+                let mut writer = Writer::new(Cursor::new(Vec::new()));
+                border.write_xml(&mut writer, "border").unwrap();
+
+                let mut xml = Reader::from_reader(Cursor::new(writer.into_inner().into_inner()));
+                let mut buf = Vec::with_capacity(1024);
+                loop {
+                    match xml.read_event_into(&mut buf) {
+                        Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"border" => {
+                            let actual = Stylesheet::read_border(&mut xml, e, &mut None).unwrap();
+                            assert_eq!(actual, border);
+                            break;
+                        }
+                        Ok(Event::Eof) => panic!("border was not written"),
+                        _ => (),
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn test_write_fill_for_solid_foreground_only() {
+            let fill = Fill {
+                r#type: PatternFill::Solid,
+                foreground: Some(Color::Rgb(Rgb::Custom(255, 199, 206))),
+                background: None,
+            };
+            let mut writer = Writer::new(Cursor::new(Vec::new()));
+            fill.write_xml(&mut writer, "fill").unwrap();
+            let written = writer.into_inner().into_inner();
+            assert_eq!(
+                String::from_utf8(written.clone()).unwrap(),
+                r#"<fill><patternFill patternType="solid"><fgColor rgb="FFFFC7CE"/></patternFill></fill>"#
+            );
+
+            let mut xml = Reader::from_reader(Cursor::new(written));
+            let mut buf = Vec::with_capacity(1024);
+            loop {
+                match xml.read_event_into(&mut buf) {
+                    Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"fill" => {
+                        let actual = Stylesheet::read_fill(&mut xml, e.name(), &mut None).unwrap();
+                        assert_eq!(actual, fill);
+                        break;
+                    }
+                    Ok(Event::Eof) => panic!("fill was not written"),
+                    _ => (),
+                }
+            }
+        }
+
+        #[test]
+        fn test_pattern_fill_round_trip() {
+            let style = init_from_xml(
+                r#"<cellXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellXfs><dxfs count="2"><dxf><fill><patternFill patternType="darkTrellis"><fgColor rgb="FFFF0000"/><bgColor indexed="64"/></patternFill></fill></dxf><dxf><fill><patternFill patternType="lightUp"/></fill></dxf></dxfs>"#,
+            );
+            let trellis = style.get_differential_ref_from_key(0).unwrap();
+            assert_eq!(
+                trellis.fill.as_ref().unwrap().r#type,
+                PatternFill::DarkTrellis
+            );
+            let light_up = style.get_differential_ref_from_key(1).unwrap();
+            assert_eq!(light_up.fill.as_ref().unwrap().r#type, PatternFill::LightUp);
+
+            let written = write(&style);
+            let actual = String::from_utf8(written.clone()).unwrap();
+            assert!(actual.contains(r#"<patternFill patternType="darkTrellis"><fgColor rgb="FFFF0000"/><bgColor indexed="64"/></patternFill>"#));
+            assert!(actual.contains(r#"<patternFill patternType="lightUp"/>"#));
+            let style = init_from_bytes(&written);
+            assert_eq!(style.get_differential_ref_from_key(0), Some(trellis));
+            assert_eq!(style.get_differential_ref_from_key(1), Some(light_up));
+
+            for (pattern, val) in PATTERN_FILLS {
+                assert_eq!(pattern.to_string(), val);
+                assert_eq!(PatternFill::try_from(val).unwrap(), pattern);
+            }
+        }
+
+        #[test]
+        fn test_write_default_background_for_solid_fills() {
+            let mut style = init("tests/workbook03.xlsx");
+            let mut writer = Writer::new(Cursor::new(Vec::new()));
+            style.write_xml(&mut writer, "styleSheet").unwrap();
+            let actual = String::from_utf8(writer.into_inner().into_inner()).unwrap();
+            assert!(actual.contains(
+                r#"<patternFill patternType="solid"><fgColor rgb="FFC6EFCE"/><bgColor indexed="64"/></patternFill>"#
+            ));
+
+            // Suppressed when writing exactly what was read
+            style.set_exact_fidelity(true);
+            let mut writer = Writer::new(Cursor::new(Vec::new()));
+            style.write_xml(&mut writer, "styleSheet").unwrap();
+            let actual = String::from_utf8(writer.into_inner().into_inner()).unwrap();
+            assert!(actual.contains(
+                r#"<patternFill patternType="solid"><fgColor rgb="FFC6EFCE"/></patternFill>"#
+            ));
+            assert!(!actual.contains(r#"<bgColor indexed="64"/>"#));
+        }
+
+        #[test]
+        fn test_alignment_round_trip() {
+            let style = init_from_xml(
+                r#"<cellXfs count="2"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"><alignment horizontal="centerContinuous"/></xf><xf numFmtId="0" fontId="0" fillId="0" borderId="0"><alignment vertical="justify"/></xf></cellXfs>"#,
+            );
+            let written = write(&style);
+            let actual = String::from_utf8(written.clone()).unwrap();
+            assert!(actual.contains(r#"<alignment horizontal="centerContinuous"/>"#));
+            assert!(actual.contains(r#"<alignment vertical="justify"/>"#));
+
+            let style = init_from_bytes(&written);
+            assert_eq!(
+                style.get_cell_ref_from_key(0).unwrap().align,
+                Some(Alignment {
+                    halign: HorizontalAlignment::CenterContinuous,
+                    ..Default::default()
+                })
+            );
+            assert_eq!(
+                style.get_cell_ref_from_key(1).unwrap().align,
+                Some(Alignment {
+                    valign: VerticalAlignment::Justify,
+                    ..Default::default()
+                })
+            );
+        }
+
+        #[test]
+        fn test_text_rotation_round_trip() {
+            let style = init_from_xml(
+                r#"<cellXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"><alignment textRotation="90" shrinkToFit="1" readingOrder="2"/></xf></cellXfs>"#,
+            );
+            let expected = Some(Alignment {
+                text_rotation: 90,
+                shrink_to_fit: true,
+                reading_order: ReadingOrder::RightToLeft,
+                ..Default::default()
+            });
+            assert_eq!(style.get_cell_ref_from_key(0).unwrap().align, expected);
+
+            let written = write(&style);
+            assert_eq!(
+                init_from_bytes(&written)
+                    .get_cell_ref_from_key(0)
+                    .unwrap()
+                    .align,
+                expected
+            );
+
+            let mut align = Alignment::default();
+            assert!(align.set_text_rotation(180).is_ok());
+            assert!(align.set_text_rotation(255).is_ok());
+            assert!(matches!(
+                align.set_text_rotation(181),
+                Err(XcelmateError::InvalidTextRotation(181))
+            ));
+            assert_eq!(align.text_rotation, 255);
+        }
+
+        #[test]
+        fn test_build_style_with_quote_prefix() {
+            let mut style = init_from_xml("");
+            let xf = StyleBuilder::new()
+                .font(style.get_font_ref_from_key(0).unwrap())
+                .fill(style.get_fill_ref_from_key(0).unwrap())
+                .border(style.get_border_ref_from_key(0).unwrap())
+                .quote_prefix(true)
+                .build();
+            style.add_cell_ref_to_table(Arc::new(xf));
+
+            let actual = String::from_utf8(write(&style)).unwrap();
+            assert!(actual.contains(
+                r#"<cellXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0" quotePrefix="1"/></cellXfs>"#
+            ));
+        }
+
+        #[test]
+        fn test_build_style_allocates_number_format_id() {
+            let mut style = init_from_xml("");
+            let build = |style: &Stylesheet| {
+                StyleBuilder::new()
+                    .font(style.get_font_ref_from_key(0).unwrap())
+                    .fill(style.get_fill_ref_from_key(0).unwrap())
+                    .border(style.get_border_ref_from_key(0).unwrap())
+                    .number_format(Arc::new(NumberFormat::custom("0.000").unwrap()))
+                    .build()
+            };
+            let xf = style.add_cell_ref_to_table(Arc::new(build(&style)));
+            assert_eq!(xf.number_format.as_ref().unwrap().id, Some(164));
+            // The same code reuses the allocated id
+            let xf = style.add_cell_ref_to_table(Arc::new(CellXf {
+                quote_prefix: true,
+                ..build(&style)
+            }));
+            assert_eq!(xf.number_format.as_ref().unwrap().id, Some(164));
+
+            let diff_xf = style.add_differential_ref_to_table(Arc::new(DiffXf {
+                number_format: Some(NumberFormat::custom("0.0000").unwrap()),
+                ..Default::default()
+            }));
+            assert_eq!(diff_xf.number_format.as_ref().unwrap().id, Some(165));
+
+            let actual = String::from_utf8(write(&style)).unwrap();
+            assert!(actual.contains(
+                r#"<cellXfs count="2"><xf numFmtId="164" fontId="0" fillId="0" borderId="0"/>"#
+            ));
+            assert!(actual.contains(r#"<dxf><numFmt numFmtId="165" formatCode="0.0000"/></dxf>"#));
+        }
+
+        #[test]
+        fn test_number_format_validation() {
+            assert!(NumberFormat::new(164, r##""$"#,##0.00;[Red]\("$"#,##0.00\)"##).is_ok());
+            assert!(NumberFormat::new(164, r##"[$-409]mmmm d, yyyy"##).is_ok());
+            assert!(NumberFormat::new(164, r##""[" 0"##).is_ok());
+            assert!(NumberFormat::new(164, r##""$#,##0.00"##).is_err());
+            assert!(NumberFormat::new(164, r##"[Red0.00"##).is_err());
+            assert!(NumberFormat::new(164, r##"[[Red]]0.00"##).is_err());
+            assert!(NumberFormat::new(164, r##"0.00]"##).is_err());
+            assert!(NumberFormat::new(164, r##"0.00\"##).is_err());
+        }
+
+        #[test]
+        fn test_number_format_escaping_round_trip() {
+            let mut style = init_from_xml("");
+            let numfmt = Arc::new(
+                NumberFormat::new(164, r##""$"#,##0.00 "R&D";[Red]\("$"#,##0.00\)"##).unwrap(),
+            );
+            style.add_number_format_ref_to_table(numfmt.clone());
+
+            let written = write(&style);
+            let actual = String::from_utf8(written.clone()).unwrap();
+            assert!(actual.contains(
+                r##"formatCode="&quot;$&quot;#,##0.00 &quot;R&amp;D&quot;;[Red]\(&quot;$&quot;#,##0.00\)""##
+            ));
+
+            let style = init_from_bytes(&written);
+            assert_eq!(style.get_number_format_ref_from_key(164), Some(numfmt));
+        }
+
+        #[test]
+        fn test_remove_unused_styles() {
+            let mut style = init_from_bytes(
+                br#"<styleSheet><fonts count="3"><font><sz val="11"/></font><font><sz val="12"/></font><font><sz val="13"/></font></fonts><fills count="3"><fill><patternFill patternType="none"/></fill><fill><patternFill patternType="gray125"/></fill><fill><patternFill patternType="solid"><fgColor rgb="FFFFC7CE"/></patternFill></fill></fills><borders count="1"><border><left/></border></borders><cellXfs count="3"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/><xf numFmtId="0" fontId="1" fillId="1" borderId="0"/><xf numFmtId="0" fontId="2" fillId="2" borderId="0"/></cellXfs></styleSheet>"#,
+            );
+            for i in 0..3 {
+                style.add_font_ref_to_table(Arc::new(FontProperty {
+                    font: format!("Unused {i}"),
+                    ..Default::default()
+                }));
+            }
+            assert_eq!(style.fonts.len(), 6);
+
+            // Every cell style in use only drops the extra fonts
+            let remap = style.remove_unused(&UsedStyleKeys {
+                cell_xfs: [0, 1, 2].into(),
+                ..Default::default()
+            });
+            assert_eq!(remap.cell_xfs, [(0, 0), (1, 1), (2, 2)].into());
+            assert_eq!(style.fonts.len(), 3);
+            assert!(style.fonts.left_values().all(|f| f.font.is_empty()));
+
+            // Unused cell style is dropped along with what only it referenced
+            let styled = style.get_cell_ref_from_key(2).unwrap();
+            let remap = style.remove_unused(&UsedStyleKeys {
+                cell_xfs: [2].into(),
+                ..Default::default()
+            });
+            assert_eq!(remap.cell_xfs, [(0, 0), (2, 1)].into());
+            assert_eq!(style.get_cell_ref_from_key(1), Some(styled.clone()));
+            assert_eq!(style.fonts.len(), 2);
+            assert_eq!(style.get_key_from_font_ref(styled.font.clone()), Some(1));
+            // Mandatory fills are always kept
+            assert_eq!(style.fills.len(), 3);
+            assert_eq!(style.get_key_from_fill_ref(styled.fill.clone()), Some(2));
+        }
+
+        #[test]
+        fn test_remove_unused_differential_styles() {
+            let mut style = init_from_xml(
+                r#"<cellXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellXfs><dxfs count="3"><dxf><font><b/></font></dxf><dxf><font><i/></font></dxf><dxf><font><u/></font></dxf></dxfs>"#,
+            );
+            let underline = style.get_differential_ref_from_key(2).unwrap();
+
+            // The middle dxf is dropped so the last one moves up
+            let remap = style.remove_unused(&UsedStyleKeys {
+                diff_xfs: [0, 2].into(),
+                ..Default::default()
+            });
+            assert_eq!(remap.diff_xfs, [(0, 0), (2, 1)].into());
+            assert_eq!(style.diff_xf.len(), 2);
+            assert_eq!(style.get_differential_ref_from_key(1), Some(underline));
+        }
+
+        #[test]
+        fn test_read_color_attribute_order() {
+            let style = init_from_xml(
+                r#"<dxfs count="1"><dxf><font><color tint="0.5" theme="4"/></font><fill><patternFill patternType="solid"><fgColor indexed="10"/><bgColor auto="1"/></patternFill></fill></dxf></dxfs>"#,
+            );
+            let dxf = style.diff_xf.get_by_right(&0).unwrap();
+            assert_eq!(
+                dxf.font.as_ref().unwrap().color,
+                Some(Color::Theme {
+                    id: 4,
+                    tint: Some("0.5".into())
+                })
+            );
+            let fill = dxf.fill.as_ref().unwrap();
+            assert_eq!(fill.foreground, Some(Color::Index(10)));
+            assert_eq!(fill.background, Some(Color::Auto));
+        }
+
+        #[test]
+        fn test_auto_color_round_trip() {
+            let style = init_from_xml(
+                r#"<fonts count="2"><font><sz val="11"/><color auto="1"/><name val="Calibri"/></font><font><sz val="11"/><color auto="0"/><name val="Calibri"/></font></fonts>"#,
+            );
+            // Both fonts follow the default one
+            assert_eq!(
+                style.get_font_ref_from_key(1).unwrap().color,
+                Some(Color::Auto)
+            );
+            // An explicitly disabled flag carries no color
+            assert_eq!(style.get_font_ref_from_key(2).unwrap().color, None);
+
+            let written = String::from_utf8(write(&style)).unwrap();
+            assert!(written.contains(r#"<color auto="1"/>"#));
+            assert_eq!(
+                init_from_bytes(written.as_bytes())
+                    .get_font_ref_from_key(1)
+                    .unwrap()
+                    .color,
+                Some(Color::Auto)
+            );
+        }
+
+        #[test]
+        fn test_custom_palette_round_trip() {
+            let style = init_from_xml(
+                r#"<colors><indexedColors><rgbColor rgb="FF000000"/><rgbColor rgb="FF123456"/></indexedColors><mruColors><color rgb="FFABCDEF"/><color theme="4"/></mruColors></colors>"#,
+            );
+            assert_eq!(
+                style.resolve_indexed_color(1),
+                Rgb::Custom(0x12, 0x34, 0x56)
+            );
+            // Indices the custom palette lacks fall back to the legacy palette
+            assert_eq!(style.resolve_indexed_color(2), Rgb::Custom(0xFF, 0, 0));
+            assert_eq!(
+                style.mru_colors,
+                Some(vec![
+                    Color::Rgb(Rgb::Custom(0xAB, 0xCD, 0xEF)),
+                    Color::Theme { id: 4, tint: None }
+                ])
+            );
+
+            let xml = String::from_utf8(write(&style)).unwrap();
+            assert!(xml.contains(r#"<colors><indexedColors><rgbColor rgb="FF000000"/><rgbColor rgb="FF123456"/></indexedColors><mruColors><color rgb="FFABCDEF"/><color theme="4"/></mruColors></colors>"#));
+            let reread = init_from_bytes(xml.as_bytes());
+            assert_eq!(reread.custom_palette, style.custom_palette);
+            assert_eq!(reread.mru_colors, style.mru_colors);
+        }
+
+        #[test]
+        fn test_save_pretty() {
+            let mut style = init("tests/workbook04.xlsx");
+            let mut saved = |options: &SaveOptions| {
+                let mut zip = ZipWriter::new(Cursor::new(Vec::<u8>::new()));
+                style.save(&mut zip, options).unwrap();
+                let mut zip = ZipArchive::new(zip.finish().unwrap()).unwrap();
+                let mut xml = String::new();
+                zip.by_name("xl/styles.xml")
+                    .unwrap()
+                    .read_to_string(&mut xml)
+                    .unwrap();
+                xml
+            };
+
+            let compact = saved(&SaveOptions::default());
+            let pretty = saved(&SaveOptions::default().pretty(true));
+            assert!(!compact.contains('\n'));
+            assert!(pretty.contains("\n  <fonts"));
+        }
+
+        #[test]
+        fn test_save_declaration() {
+            let mut style = init("tests/workbook04.xlsx");
+            let mut saved = |options: &SaveOptions| {
+                let mut zip = ZipWriter::new(Cursor::new(Vec::<u8>::new()));
+                style.save(&mut zip, options).unwrap();
+                let mut zip = ZipArchive::new(zip.finish().unwrap()).unwrap();
+                let mut xml = Vec::new();
+                zip.by_name("xl/styles.xml")
+                    .unwrap()
+                    .read_to_end(&mut xml)
+                    .unwrap();
+                xml
+            };
+
+            let plain = saved(&SaveOptions::default());
+            assert!(
+                plain.starts_with(br#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#)
+            );
+
+            let options = SaveOptions::default().part_declaration(
+                "xl/styles.xml",
+                XmlDeclaration {
+                    bom: true,
+                    standalone: false,
+                },
+            );
+            let bom = saved(&options);
+            assert_eq!(&bom[..3], b"\xEF\xBB\xBF");
+            assert!(bom[3..].starts_with(br#"<?xml version="1.0" encoding="UTF-8"?>"#));
+            assert_eq!(plain[plain.len() - 200..], bom[bom.len() - 200..]);
+        }
+
+        #[test]
+        fn test_format_state_from_option() {
+            for (option, state) in [
+                (Some(true), FormatState::Enabled),
+                (Some(false), FormatState::Disabled),
+                (None, FormatState::None),
+            ] {
+                assert_eq!(FormatState::from(option), state);
+                assert_eq!(Option::<bool>::from(state), option);
+            }
+
+            // Not set is not emitted
+            let font = FontProperty {
+                bold: Some(true).into(),
+                italic: None.into(),
+                ..Default::default()
+            };
+            let mut writer = Writer::new(Cursor::new(Vec::new()));
+            font.write_xml(&mut writer, "font").unwrap();
+            let actual = String::from_utf8(writer.into_inner().into_inner()).unwrap();
+            assert!(actual.contains("<b/>"));
+            assert!(!actual.contains("<i"));
+        }
+
+        #[test]
+        fn test_font_scheme_round_trip() {
+            for scheme in [
+                None,
+                Some(FontScheme::None),
+                Some(FontScheme::Major),
+                Some(FontScheme::Minor),
+            ] {
+                let font = FontProperty {
+                    scheme: scheme.clone(),
+                    ..Default::default()
+                };
+                let mut writer = Writer::new(Cursor::new(Vec::new()));
+                font.write_xml(&mut writer, "font").unwrap();
+
+                let mut xml = Reader::from_reader(Cursor::new(writer.into_inner().into_inner()));
+                let mut buf = Vec::new();
+                match xml.read_event_into(&mut buf) {
+                    Ok(Event::Start(ref e)) => {
+                        let actual = Stylesheet::read_font(&mut xml, e.name(), &mut None).unwrap();
+                        assert_eq!(actual.scheme, scheme);
+                    }
+                    event => panic!("expected a font start tag, got {event:?}"),
+                }
+            }
+
+            // An explicit `none` is written back rather than dropped
+            let font = FontProperty {
+                scheme: Some(FontScheme::None),
+                ..Default::default()
+            };
+            let mut writer = Writer::new(Cursor::new(Vec::new()));
+            font.write_xml(&mut writer, "font").unwrap();
+            let actual = String::from_utf8(writer.into_inner().into_inner()).unwrap();
+            assert_eq!(actual, r#"<font><scheme val="none"/></font>"#);
+
+            let mut xml = Reader::from_reader(Cursor::new(r#"<font><scheme val="body"/></font>"#));
+            let mut buf = Vec::new();
+            match xml.read_event_into(&mut buf) {
+                Ok(Event::Start(ref e)) => {
+                    assert!(Stylesheet::read_font(&mut xml, e.name(), &mut None).is_err())
+                }
+                event => panic!("expected a font start tag, got {event:?}"),
+            }
+        }
+
+        #[test]
+        fn test_font_family_validation() {
+            let mut font = FontProperty::default();
+            font.set_family(2).unwrap();
+            assert_eq!(font.family, FontFamily::Swiss);
+            assert!(font.set_family(6).is_err());
+            assert_eq!(font.family, FontFamily::Swiss);
+
+            // Out of range values are kept but only flagged when asked for
+            let style = init_from_xml(
+                r#"<dxfs count="1"><dxf><font><family val="9"/></font></dxf></dxfs>"#,
+            );
+            assert!(style.warnings().is_empty());
+            let mut collecting = Stylesheet::default();
+            collecting.set_collect_warnings(true);
+            let collecting = read_into(collecting, &write(&style));
+            assert_eq!(
+                collecting.warnings(),
+                [Warning::InvalidValue {
+                    part: "xl/styles.xml".into(),
+                    element: "family".into(),
+                    value: "9".into(),
+                }]
+            );
+            let dxf = style.diff_xf.get_by_right(&0).unwrap();
+            assert_eq!(dxf.font.as_ref().unwrap().family, FontFamily::Other(9));
+            let actual = String::from_utf8(write(&style)).unwrap();
+            assert!(actual.contains(r#"<family val="9"/>"#));
+        }
+
+        #[test]
+        fn test_get_or_insert_styles() {
+            let mut style = init_from_xml("");
+
+            // Existing entries are reused
+            let fill = (*style.get_fill_ref_from_key(0).unwrap()).clone();
+            assert_eq!(style.get_or_insert_fill(fill), 0);
+            let border = (*style.get_border_ref_from_key(0).unwrap()).clone();
+            assert_eq!(style.get_or_insert_border(border), 0);
+
+            // New entries are added once
+            let fill = Fill {
+                r#type: PatternFill::Solid,
+                foreground: Some(Color::Index(10)),
+                background: None,
+            };
+            assert_eq!(style.get_or_insert_fill(fill.clone()), 1);
+            assert_eq!(style.get_or_insert_fill(fill), 1);
+            let border = Border {
+                left: BorderRegion {
+                    style: Some(BorderStyle::Thin),
+                    color: None,
+                },
+                ..Default::default()
+            };
+            assert_eq!(style.get_or_insert_border(border.clone()), 1);
+            assert_eq!(style.get_or_insert_border(border), 1);
+
+            // Custom number formats are allocated after the reserved ids
+            assert_eq!(style.get_or_insert_number_format("0.000").unwrap(), 164);
+            assert_eq!(style.get_or_insert_number_format("0.000").unwrap(), 164);
+            assert_eq!(style.get_or_insert_number_format("0.0000").unwrap(), 165);
+            assert!(style.get_or_insert_number_format(r#""0.0"#).is_err());
+
+            // Builtin codes resolve to their builtin id and are never declared
+            assert_eq!(style.get_or_insert_number_format("0.00").unwrap(), 2);
+            assert_eq!(style.get_or_insert_number_format("mm-dd-yy").unwrap(), 14);
+            assert_eq!(
+                style.get_number_format_ref_from_key(14),
+                Some(Arc::new(NumberFormat::new(14, "mm-dd-yy").unwrap()))
+            );
+            assert_eq!(style.get_or_insert_number_format("0.00000").unwrap(), 166);
+            let actual = String::from_utf8(write(&style)).unwrap();
+            assert!(actual.contains(r#"<numFmts count="3">"#));
+        }
+
+        #[test]
+        fn test_allocate_custom_number_format_ids() {
+            let mut style = init_from_xml("");
+            let first = style
+                .add_number_format_ref_to_table(Arc::new(NumberFormat::custom("0.000").unwrap()));
+            let second = style
+                .add_number_format_ref_to_table(Arc::new(NumberFormat::custom("0.0000").unwrap()));
+            assert_eq!(first.id(), 164);
+            assert_eq!(second.id(), 165);
+            assert_eq!(style.get_number_format_ref_from_key(164), Some(first));
+            assert_eq!(style.get_number_format_ref_from_key(165), Some(second));
+
+            // An explicit builtin id is kept whatever its code is spelled as
+            let general = style
+                .add_number_format_ref_to_table(Arc::new(NumberFormat::new(0, "general").unwrap()));
+            assert_eq!(general.id(), 0);
+            assert_eq!(style.get_number_format_ref_from_key(166), None);
+
+            assert!(matches!(
+                NumberFormat::new(164, ""),
+                Err(XcelmateError::InvalidNumberFormat(_))
+            ));
+            assert!(NumberFormat::custom("").is_err());
+        }
+
+        #[test]
+        fn test_with_defaults() {
+            let style = Stylesheet::with_defaults();
+            assert_eq!(
+                style.get_fill_ref_from_key(0).unwrap().r#type,
+                PatternFill::None
+            );
+            assert_eq!(
+                style.get_fill_ref_from_key(1).unwrap().r#type,
+                PatternFill::Gray
+            );
+            assert_eq!(style.fills.len(), 2);
+            assert_eq!(style.fonts.len(), 1);
+            assert_eq!(style.borders.len(), 1);
+
+            let xf = style.get_cell_ref_from_key(0).unwrap();
+            assert_eq!(xf.font, style.get_font_ref_from_key(0).unwrap());
+            assert_eq!(xf.fill, style.get_fill_ref_from_key(0).unwrap());
+            assert_eq!(xf.border, style.get_border_ref_from_key(0).unwrap());
+        }
+
+        #[test]
+        fn test_round_trip() {
+            let expected = br##"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:mc="http://schemas.openxmlformats.org/markup-compatibility/2006" mc:Ignorable="x14ac x16r2 xr xr9" xmlns:x14ac="http://schemas.microsoft.com/office/spreadsheetml/2009/9/ac" xmlns:x16r2="http://schemas.microsoft.com/office/spreadsheetml/2015/02/main" xmlns:xr="http://schemas.microsoft.com/office/spreadsheetml/2014/revision" xmlns:xr9="http://schemas.microsoft.com/office/spreadsheetml/2016/revision9">
+    <numFmts count="1"><numFmt numFmtId="164" formatCode="&quot;$&quot;#,##0.00"/></numFmts>
+    <fonts count="2">
+        <font><sz val="11"/><color theme="1"/><name val="Calibri"/><family val="2"/><scheme val="minor"/></font>
+        <font><b/><sz val="14"/><color rgb="FFFF0000"/><name val="Calibri"/><family val="2"/><scheme val="minor"/></font>
+    </fonts>
+    <fills count="2">
+        <fill><patternFill patternType="none"/></fill>
+        <fill><patternFill patternType="gray125"/></fill>
+    </fills>
+    <borders count="1">
+        <border><left style="thin"><color indexed="64"/></left><bottom style="double"><color theme="4"/></bottom></border>
+    </borders>
+    <cellStyleXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellStyleXfs>
+    <cellXfs count="2">
+        <xf numFmtId="0" fontId="0" fillId="0" borderId="0"/>
+        <xf numFmtId="164" fontId="1" fillId="1" borderId="0" quotePrefix="1"><alignment wrapText="1" horizontal="center"/></xf>
+    </cellXfs>
+    <cellStyles count="1"><cellStyle name="Normal" xfId="0" builtinId="0"/></cellStyles>
+    <dxfs count="0"/>
+    <tableStyles count="0" defaultTableStyle="TableStyleMedium2" defaultPivotStyle="PivotStyleLight16"/>
+    <colors><mruColors><color rgb="FFFF0000"/></mruColors></colors>
+</styleSheet>"##;
+            let style = init_from_bytes(expected);
+            assert_xml_eq(&write(&style), expected);
+        }
+
+        #[test]
+        fn test_table_style_uid() {
+            // Read uids are kept as is
+            let style = init("tests/workbook04.xlsx");
+            let actual = String::from_utf8(write(&style)).unwrap();
+            assert!(actual.contains(r#"xr9:uid="{00000000-0011-0000-FFFF-FFFF00000000}""#));
+
+            // New styles are given a fresh uid
+            let mut style = init_from_xml("");
+            let first = style.add_custom_table_style(
+                "First",
+                Arc::new(TableCustomStyle {
+                    name: "First".into(),
+                    ..Default::default()
+                }),
+            );
+            let second = style.add_custom_table_style(
+                "Second",
+                Arc::new(TableCustomStyle {
+                    name: "Second".into(),
+                    ..Default::default()
+                }),
+            );
+            assert_ne!(first.uid, second.uid);
+            for uid in [&first.uid, &second.uid] {
+                assert_eq!(uid.len(), 38);
+                assert!(uid.starts_with('{') && uid.ends_with('}'));
+                assert!(uid[1..37]
+                    .chars()
+                    .all(|c| c == '-' || c.is_ascii_hexdigit()));
+            }
+            let actual = String::from_utf8(write(&style)).unwrap();
+            assert!(actual.contains(&format!(r#"xr9:uid="{}""#, first.uid)));
+        }
+
+        #[test]
+        fn test_write_is_deterministic() {
+            let build = |order: &[usize]| {
+                let mut style = Stylesheet::with_defaults();
+                for idx in order {
+                    let code = format!("0.{}", "0".repeat(*idx + 1));
+                    style.add_number_format_ref_to_table(Arc::new(
+                        NumberFormat::new(164 + *idx as u32, &code).unwrap(),
+                    ));
+                    let name = format!("Table Style {idx}");
+                    style.add_custom_table_style(
+                        &name,
+                        Arc::new(TableCustomStyle {
+                            name: name.clone(),
+                            uid: format!("{{00000000-0000-0000-0000-00000000000{idx}}}"),
+                            ..Default::default()
+                        }),
+                    );
+                }
+                write(&style)
+            };
+
+            // The same styles added in any order are written the same
+            let expected = build(&[0, 1, 2, 3, 4]);
+            assert_eq!(build(&[4, 2, 0, 3, 1]), expected);
+            assert_eq!(build(&[3, 4, 1, 2, 0]), expected);
+        }
+
+        #[test]
+        fn test_differential_fill_without_pattern() {
+            let dxfs = r#"<dxfs count="2"><dxf><fill><patternFill><bgColor rgb="FFFFC7CE"/></patternFill></fill></dxf><dxf><fill><patternFill><fgColor rgb="FF9C0006"/></patternFill></fill></dxf></dxfs>"#;
+            let style = init_from_xml(dxfs);
+
+            let actual = String::from_utf8(write(&style)).unwrap();
+            assert!(actual.contains(dxfs));
+        }
+
+        #[test]
+        fn test_absent_font_color() {
+            let style = init_from_xml(
+                r#"<dxfs count="2"><dxf><font><b/></font></dxf><dxf><font><b/><color theme="1"/></font></dxf></dxfs>"#,
+            );
+            let absent = style.diff_xf.get_by_right(&0).unwrap();
+            let explicit = style.diff_xf.get_by_right(&1).unwrap();
+            assert_eq!(absent.font.as_ref().unwrap().color, None);
+            assert_eq!(
+                explicit.font.as_ref().unwrap().color,
+                Some(Color::Theme { id: 1, tint: None })
+            );
+
+            let actual = String::from_utf8(write(&style)).unwrap();
+            assert!(actual.contains(
+                r#"<dxfs count="2"><dxf><font><b/></font></dxf><dxf><font><b/><color theme="1"/></font></dxf></dxfs>"#
+            ));
+        }
+
+        #[test]
+        fn test_merge_from() {
+            let mut style = Stylesheet::with_defaults();
+            style.add_font_ref_to_table(Arc::new(FontProperty {
+                font: "Times New Roman".into(),
+                ..Default::default()
+            }));
+            style.get_or_insert_number_format("0.000").unwrap();
+
+            let mut other = Stylesheet::with_defaults();
+            let arial = other.add_font_ref_to_table(Arc::new(FontProperty {
+                font: "Arial".into(),
+                bold: FormatState::Enabled,
+                ..Default::default()
+            }));
+            let number_format = other
+                .add_number_format_ref_to_table(Arc::new(NumberFormat::new(164, "0%").unwrap()));
+            other.add_cell_ref_to_table(Arc::new(
+                StyleBuilder::new()
+                    .font(arial.clone())
+                    .fill(other.get_fill_ref_from_key(0).unwrap())
+                    .border(other.get_border_ref_from_key(0).unwrap())
+                    .number_format(number_format)
+                    .build(),
+            ));
+
+            let remap = style.merge_from(&other);
+            // The shared default font is reused and the new one is appended
+            assert_eq!(remap.fonts, BTreeMap::from([(0, 0), (1, 2)]));
+            assert_eq!(style.get_font_ref_from_key(2), Some(arial));
+            assert_eq!(remap.fills, BTreeMap::from([(0, 0), (1, 1)]));
+            assert_eq!(remap.borders, BTreeMap::from([(0, 0)]));
+            // The taken id 164 moves to the next free custom id
+            assert_eq!(remap.number_formats, BTreeMap::from([(164, 165)]));
+            assert_eq!(remap.cell_xfs, BTreeMap::from([(0, 0), (1, 1)]));
+            let xf = style.get_cell_ref_from_key(1).unwrap();
+            assert_eq!(style.get_key_from_font_ref(xf.font.clone()), Some(2));
+            assert_eq!(xf.number_format.as_ref().unwrap().id(), 165);
+
+            // Merging again adds nothing
+            let remap = style.merge_from(&other);
+            assert_eq!(remap.fonts, BTreeMap::from([(0, 0), (1, 2)]));
+            assert_eq!(remap.cell_xfs, BTreeMap::from([(0, 0), (1, 1)]));
+            assert_eq!(style.fonts.len(), 3);
+        }
+
+        #[test]
+        fn test_read_dxf_number_format_and_alignment() {
+            let style = init_from_xml(
+                r#"<dxfs count="1"><dxf><numFmt numFmtId="164" formatCode="0.0%"/><alignment horizontal="center" wrapText="1"/><protection locked="0"/></dxf></dxfs>"#,
+            );
+            let dxf = style.get_differential_ref_from_key(0).unwrap();
+            assert_eq!(
+                dxf.number_format,
+                Some(NumberFormat::new(164, "0.0%").unwrap())
+            );
+            assert_eq!(
+                dxf.align,
+                Some(Alignment {
+                    wrap: true,
+                    halign: HorizontalAlignment::Center,
+                    ..Default::default()
+                })
+            );
+            assert_eq!(
+                dxf.protection,
+                Some(Protection {
+                    locked: FormatState::Disabled,
+                    hidden: FormatState::None,
+                })
+            );
+
+            let written = write(&style);
+            assert_eq!(
+                init_from_bytes(&written).get_differential_ref_from_key(0),
+                Some(dxf)
+            );
+        }
+
+        #[test]
+        fn test_collect_unknown_attributes() {
+            let styles = br#"<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><fonts count="1"><font><sz val="11" futureSize="12"/></font></fonts><fills count="1"><fill><patternFill patternType="solid" futurePattern="1"><fgColor rgb="FFFF0000" futureColor="1"/></patternFill></fill></fills><borders count="1"><border><left/></border></borders><cellXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0" futureFlag="1"/></cellXfs><dxfs count="1"><dxf><font><family val="9"/></font></dxf></dxfs></styleSheet>"#;
+
+            // Nothing is recorded unless asked for
+            assert!(init_from_bytes(styles).warnings().is_empty());
+
+            let mut style = Stylesheet::default();
+            style.set_collect_warnings(true);
+            let style = read_into(style, styles);
+            assert_eq!(
+                style.warnings(),
+                [
+                    Warning::UnknownAttribute {
+                        part: "xl/styles.xml".into(),
+                        element: "sz".into(),
+                        attr: "futureSize".into(),
+                    },
+                    Warning::UnknownAttribute {
+                        part: "xl/styles.xml".into(),
+                        element: "patternFill".into(),
+                        attr: "futurePattern".into(),
+                    },
+                    Warning::UnknownAttribute {
+                        part: "xl/styles.xml".into(),
+                        element: "fgColor".into(),
+                        attr: "futureColor".into(),
+                    },
+                    Warning::UnknownAttribute {
+                        part: "xl/styles.xml".into(),
+                        element: "xf".into(),
+                        attr: "futureFlag".into(),
+                    },
+                    Warning::InvalidValue {
+                        part: "xl/styles.xml".into(),
+                        element: "family".into(),
+                        value: "9".into(),
+                    }
+                ]
+            );
+        }
+
+        #[test]
+        fn test_resolve_colors() {
+            let style = init_from_xml("");
+            assert_eq!(style.resolve_indexed_color(0), Rgb::Custom(0, 0, 0));
+            assert_eq!(style.resolve_indexed_color(2), Rgb::Custom(0xFF, 0, 0));
+            assert_eq!(style.resolve_indexed_color(64), Rgb::Custom(0, 0, 0));
+            assert_eq!(
+                style.resolve_indexed_color(65),
+                Rgb::Custom(0xFF, 0xFF, 0xFF)
+            );
+
+            let file = File::open("tests/workbook04.xlsx").unwrap();
+            let mut theme = Theme::default();
+            theme
+                .read_theme(&mut ZipArchive::new(file).unwrap())
+                .unwrap();
+            assert_eq!(
+                Color::Index(10).to_rgb(&style, &theme),
+                Some(Rgb::Custom(0xFF, 0, 0))
+            );
+            assert_eq!(
+                Color::Rgb(Rgb::Custom(1, 2, 3)).to_rgb(&style, &theme),
+                Some(Rgb::Custom(1, 2, 3))
+            );
+            assert_eq!(
+                Color::Theme { id: 4, tint: None }.to_rgb(&style, &theme),
+                Some(Rgb::Custom(0x23, 0x77, 0xB6))
+            );
+            assert_eq!(
+                Color::Theme {
+                    id: 4,
+                    tint: Some("0.4".into())
+                }
+                .to_rgb(&style, &theme),
+                Some(Rgb::Custom(0x6C, 0xB0, 0xE3))
+            );
+            assert_eq!(
+                Color::Theme { id: 12, tint: None }.to_rgb(&style, &theme),
+                None
+            );
+            assert_eq!(Color::Auto.to_rgb(&style, &theme), None);
+        }
+
+        #[test]
+        fn test_write_more_fills_than_fonts() {
+            let mut style = Stylesheet::with_defaults();
+            for size in 12..16 {
+                style.add_font_ref_to_table(Arc::new(FontProperty {
+                    size: size.to_string(),
+                    ..Default::default()
+                }));
+            }
+            for idx in 10..16 {
+                style.get_or_insert_fill(Fill {
+                    r#type: PatternFill::Solid,
+                    foreground: Some(Color::Index(idx)),
+                    background: None,
+                });
+            }
+            assert_eq!(style.fonts.len(), 5);
+            assert_eq!(style.fills.len(), 8);
+
+            let actual = String::from_utf8(write(&style)).unwrap();
+            assert!(actual.contains(r#"<fills count="8">"#));
+            assert_eq!(actual.matches("<fill>").count(), 8);
+            assert_eq!(init_from_bytes(actual.as_bytes()).fills.len(), 8);
+        }
+
+        #[test]
+        fn test_cell_style_xfs_round_trip() {
+            let style = init_from_bytes(
+                br#"<styleSheet><fonts count="3"><font><sz val="11"/></font><font><b/><sz val="15"/></font><font><sz val="11"/><color rgb="FF006100"/></font></fonts><fills count="3"><fill><patternFill patternType="none"/></fill><fill><patternFill patternType="gray125"/></fill><fill><patternFill patternType="solid"><fgColor rgb="FFC6EFCE"/><bgColor indexed="64"/></patternFill></fill></fills><borders count="1"><border><left/></border></borders><cellStyleXfs count="3"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/><xf numFmtId="0" fontId="1" fillId="0" borderId="0"/><xf numFmtId="0" fontId="2" fillId="2" borderId="0"/></cellStyleXfs><cellXfs count="2"><xf numFmtId="0" fontId="0" fillId="0" borderId="0" xfId="0"/><xf numFmtId="0" fontId="2" fillId="2" borderId="0" xfId="2"/></cellXfs><cellStyles count="3"><cellStyle name="Good" xfId="2" builtinId="26"/><cellStyle name="Heading 1" xfId="1" builtinId="16"/><cellStyle name="Normal" xfId="0" builtinId="0"/></cellStyles></styleSheet>"#,
+            );
+            assert_eq!(style.cell_style_xf.len(), 3);
+            let heading = style.get_cell_style_ref_from_key(1).unwrap();
+            assert_eq!(heading.font.bold, FormatState::Enabled);
+            let good = style.get_cell_style_ref_from_key(2).unwrap();
+            assert_eq!(good.fill, style.get_fill_ref_from_key(2).unwrap());
+            assert_eq!(style.get_cell_ref_from_key(1).unwrap().xf_id, Some(2));
+
+            let actual = String::from_utf8(write(&style)).unwrap();
+            assert!(actual.contains(
+                r#"<cellStyleXfs count="3"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/><xf numFmtId="0" fontId="1" fillId="0" borderId="0"/><xf numFmtId="0" fontId="2" fillId="2" borderId="0"/></cellStyleXfs>"#
+            ));
+            assert!(actual
+                .contains(r#"<xf numFmtId="0" fontId="2" fillId="2" borderId="0" xfId="2"/>"#));
+
+            let reread = init_from_bytes(actual.as_bytes());
+            for key in 0..3 {
+                assert_eq!(
+                    reread.get_cell_style_ref_from_key(key),
+                    style.get_cell_style_ref_from_key(key)
+                );
+            }
+
+            // Master formats that are equal keep their own index
+            let style = init_from_xml(
+                r#"<cellStyleXfs count="2"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellStyleXfs>"#,
+            );
+            assert_eq!(style.cell_style_xf.len(), 2);
+            assert!(String::from_utf8(write(&style))
+                .unwrap()
+                .contains(r#"<cellStyleXfs count="2">"#));
+        }
+
+        #[test]
+        fn test_cell_styles_round_trip() {
+            let style = init("tests/workbook03.xlsx");
+            assert_eq!(style.cell_styles.len(), 23);
+            assert_eq!(
+                style.cell_styles[8],
+                CellStyle {
+                    name: "Good".into(),
+                    xf_id: 11,
+                    builtin_id: Some(26),
+                    ..Default::default()
+                }
+            );
+            let good = style.get_cell_style_by_name("Good").unwrap();
+            assert_eq!(good, style.get_cell_style_ref_from_key(11).unwrap());
+            assert_eq!(
+                good.fill.foreground,
+                Some(Color::Rgb(Rgb::Custom(0xC6, 0xEF, 0xCE)))
+            );
+            assert_eq!(style.get_cell_style_by_name("Missing"), None);
+
+            let actual = String::from_utf8(write(&style)).unwrap();
+            assert!(actual.contains(
+                r#"<cellStyles count="23"><cellStyle name="Bad" xfId="12" builtinId="27"/>"#
+            ));
+            let reread = init_from_bytes(actual.as_bytes());
+            assert_eq!(reread.cell_styles, style.cell_styles);
+
+            // Customized presets and their revision uid are kept
+            let style = init_from_xml(
+                r#"<cellStyleXfs count="2"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellStyleXfs><cellStyles count="2"><cellStyle name="Comma" xfId="1" builtinId="3" customBuiltin="1"/><cellStyle name="Date" xfId="0" hidden="1" xr:uid="{00000000-0005-0000-0000-000005000000}"/></cellStyles>"#,
+            );
+            let actual = String::from_utf8(write(&style)).unwrap();
+            assert!(actual.contains(
+                r#"<cellStyles count="2"><cellStyle name="Comma" xfId="1" builtinId="3" customBuiltin="1"/><cellStyle name="Date" xfId="0" hidden="1" xr:uid="{00000000-0005-0000-0000-000005000000}"/></cellStyles>"#
+            ));
+        }
+
+        #[test]
+        fn test_diagonal_border_round_trip() {
+            let style = init_from_bytes(
+                br#"<styleSheet><fonts count="1"><font><sz val="11"/></font></fonts><fills count="1"><fill><patternFill patternType="none"/></fill></fills><borders count="3"><border><left/><right/><top/><bottom/><diagonal/></border><border diagonalUp="1"><left/><right/><top/><bottom/><diagonal style="thin"><color rgb="FFFF0000"/></diagonal></border><border diagonalUp="1"><left/><right/><top/><bottom/><diagonal/></border></borders><cellXfs count="2"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/><xf numFmtId="0" fontId="0" fillId="0" borderId="1"/></cellXfs></styleSheet>"#,
+            );
+            let border = style.get_cell_ref_from_key(1).unwrap().border.clone();
+            assert_eq!(
+                *border,
+                Border {
+                    diagonal: BorderRegion {
+                        style: Some(BorderStyle::Thin),
+                        color: Some(Color::Rgb(Rgb::Custom(0xFF, 0, 0))),
+                    },
+                    diagonal_up: true,
+                    ..Default::default()
+                }
+            );
+            assert_eq!(style.borders.len(), 3);
+
+            let actual = String::from_utf8(write(&style)).unwrap();
+            assert!(actual.contains(
+                r#"<border diagonalUp="1"><diagonal style="thin"><color rgb="FFFF0000"/></diagonal></border>"#
+            ));
+            // The direction survives without a diagonal style
+            let reread = init_from_bytes(actual.as_bytes());
+            assert_eq!(
+                *reread.get_border_ref_from_key(2).unwrap(),
+                Border {
+                    diagonal_up: true,
+                    ..Default::default()
+                }
+            );
+            assert_eq!(reread.get_border_ref_from_key(1).unwrap(), border);
+        }
     }
 }
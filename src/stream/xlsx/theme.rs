@@ -0,0 +1,266 @@
+//! The module holds all logic to deserialize the color scheme of the theme1.xml in the .xlsx file
+use crate::{errors::XcelmateError, stream::utils::xml_reader};
+use quick_xml::{events::Event, name::QName, Reader};
+use std::io::{BufRead, Read, Seek};
+use zip::ZipArchive;
+
+use super::stylesheet::Rgb;
+
+/// The `Theme` holds the color scheme of a workbook that `theme` colors refer to
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct Theme {
+    /// The colors in `<clrScheme>` order: dk1, lt1, dk2, lt2, accent1-6, hlink and folHlink
+    colors: Vec<Rgb>,
+}
+impl Theme {
+    pub(crate) fn read_theme<RS: Read + Seek>(
+        &mut self,
+        zip: &mut ZipArchive<RS>,
+    ) -> Result<(), XcelmateError> {
+        let mut xml = match xml_reader(zip, "xl/theme/theme1.xml") {
+            None => return Ok(()),
+            Some(x) => x?,
+        };
+        self.read_color_scheme(&mut xml)
+    }
+
+    /// Read one color for every child of `<clrScheme>` so theme indices keep their slot
+    fn read_color_scheme<B: BufRead>(&mut self, xml: &mut Reader<B>) -> Result<(), XcelmateError> {
+        let mut buf = Vec::with_capacity(1024);
+        let mut in_scheme = false;
+        loop {
+            buf.clear();
+            match xml.read_event_into(&mut buf) {
+                Ok(Event::Start(ref e)) if e.local_name().as_ref() == b"clrScheme" => {
+                    in_scheme = true
+                }
+                ////////////////////
+                // COLOR SCHEME nth-2
+                /////////////
+                Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e))
+                    if in_scheme && matches!(e.local_name().as_ref(), b"srgbClr" | b"sysClr") =>
+                {
+                    let mut val = None;
+                    let mut last_clr = None;
+                    for attr in e.attributes() {
+                        if let Ok(a) = attr {
+                            match a.key {
+                                QName(b"val") => val = Some(a.unescape_value()?.to_string()),
+                                QName(b"lastClr") => {
+                                    last_clr = Some(a.unescape_value()?.to_string())
+                                }
+                                _ => (),
+                            }
+                        }
+                    }
+                    // System colors carry the color they last resolved to, otherwise the
+                    // known system color is used
+                    let rgb = match (e.local_name().as_ref(), val.as_deref(), last_clr) {
+                        (b"srgbClr", Some(val), _) => Theme::to_rgb(val)?,
+                        (b"sysClr", _, Some(last_clr)) => Theme::to_rgb(&last_clr)?,
+                        (b"sysClr", Some("window"), None) => Rgb::Custom(0xFF, 0xFF, 0xFF),
+                        _ => Rgb::Custom(0, 0, 0),
+                    };
+                    self.colors.push(rgb);
+                }
+                Ok(Event::End(ref e)) if e.local_name().as_ref() == b"clrScheme" => break,
+                Ok(Event::Eof) => break,
+                Err(e) => return Err(XcelmateError::Xml(e)),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// The color a `theme` index refers to
+    ///
+    /// The index swaps the dark and light pairs of `<clrScheme>` so 0 is lt1, 1 is dk1,
+    /// 2 is lt2 and 3 is dk2
+    pub(crate) fn color(&self, id: u32) -> Option<Rgb> {
+        let slot = match id {
+            0 => 1,
+            1 => 0,
+            2 => 3,
+            3 => 2,
+            id => id as usize,
+        };
+        self.colors.get(slot).cloned()
+    }
+
+    /// Resolve a `theme` color with its tint the way Excel displays it
+    ///
+    /// A positive tint lightens the color towards white and a negative tint darkens it
+    /// towards black by scaling its HSL luminance. A missing theme color resolves to black
+    pub(crate) fn resolve(&self, id: u32, tint: Option<&str>) -> Rgb {
+        let (r, g, b) = match self.color(id) {
+            Some(Rgb::Custom(r, g, b)) | Some(Rgb::CustomAlpha(_, r, g, b)) => (r, g, b),
+            None => (0, 0, 0),
+        };
+        let tint = tint.and_then(|t| t.parse::<f64>().ok()).unwrap_or(0.0);
+        if tint == 0.0 {
+            return Rgb::Custom(r, g, b);
+        }
+
+        let (h, s, l) = rgb_to_hsl(r, g, b);
+        let l = if tint < 0.0 {
+            l * (1.0 + tint)
+        } else {
+            l * (1.0 - tint) + tint
+        };
+        let (r, g, b) = hsl_to_rgb(h, s, l.clamp(0.0, 1.0));
+        Rgb::Custom(r, g, b)
+    }
+
+    /// Convert a hexadecimal `RRGGBB` into RGB
+    fn to_rgb(value: &str) -> Result<Rgb, XcelmateError> {
+        let rgb = u32::from_str_radix(value, 16)?;
+        Ok(Rgb::Custom((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+    }
+}
+
+/// Convert RGB into hue, saturation and luminance each scaled to 0-1
+fn rgb_to_hsl(r: u8, g: u8, b: u8) -> (f64, f64, f64) {
+    let (r, g, b) = (r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.0;
+    if max == min {
+        return (0.0, 0.0, l);
+    }
+
+    let d = max - min;
+    let s = if l > 0.5 {
+        d / (2.0 - max - min)
+    } else {
+        d / (max + min)
+    };
+    let h = if max == r {
+        (g - b) / d + if g < b { 6.0 } else { 0.0 }
+    } else if max == g {
+        (b - r) / d + 2.0
+    } else {
+        (r - g) / d + 4.0
+    };
+    (h / 6.0, s, l)
+}
+
+/// Convert hue, saturation and luminance each scaled to 0-1 into RGB
+fn hsl_to_rgb(h: f64, s: f64, l: f64) -> (u8, u8, u8) {
+    let to_u8 = |v: f64| (v * 255.0).round() as u8;
+    if s == 0.0 {
+        return (to_u8(l), to_u8(l), to_u8(l));
+    }
+
+    let q = if l < 0.5 {
+        l * (1.0 + s)
+    } else {
+        l + s - l * s
+    };
+    let p = 2.0 * l - q;
+    let hue = |t: f64| {
+        let t = t.rem_euclid(1.0);
+        if t < 1.0 / 6.0 {
+            p + (q - p) * 6.0 * t
+        } else if t < 1.0 / 2.0 {
+            q
+        } else if t < 2.0 / 3.0 {
+            p + (q - p) * (2.0 / 3.0 - t) * 6.0
+        } else {
+            p
+        }
+    };
+    (
+        to_u8(hue(h + 1.0 / 3.0)),
+        to_u8(hue(h)),
+        to_u8(hue(h - 1.0 / 3.0)),
+    )
+}
+
+#[cfg(test)]
+mod theme_unittests {
+    use super::Theme;
+    use quick_xml::Reader;
+    use std::fs::File;
+    use zip::ZipArchive;
+
+    fn init(path: &str) -> Theme {
+        let file = File::open(path).unwrap();
+        let mut zip = ZipArchive::new(file).unwrap();
+        let mut theme = Theme::default();
+        theme.read_theme(&mut zip).unwrap();
+        theme
+    }
+
+    fn init_from_scheme(scheme: &str) -> Theme {
+        let xml = format!(
+            r#"<a:theme xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main"><a:themeElements><a:clrScheme name="Test">{scheme}</a:clrScheme></a:themeElements></a:theme>"#
+        );
+        let mut theme = Theme::default();
+        theme
+            .read_color_scheme(&mut Reader::from_reader(xml.as_bytes()))
+            .unwrap();
+        theme
+    }
+
+    mod theme_api {
+        use super::{init, init_from_scheme};
+        use crate::stream::xlsx::stylesheet::Rgb;
+
+        #[test]
+        fn read_theme() {
+            let theme = init("tests/workbook04.xlsx");
+            assert_eq!(theme.colors.len(), 12);
+            // Dark and light pairs are swapped
+            assert_eq!(theme.color(0), Some(Rgb::Custom(0xFF, 0xFF, 0xFF)));
+            assert_eq!(theme.color(1), Some(Rgb::Custom(0, 0, 0)));
+            assert_eq!(theme.color(2), Some(Rgb::Custom(0xDF, 0xE3, 0xE5)));
+            assert_eq!(theme.color(3), Some(Rgb::Custom(0x33, 0x5B, 0x74)));
+            assert_eq!(theme.color(4), Some(Rgb::Custom(0x23, 0x77, 0xB6)));
+            assert_eq!(theme.color(12), None);
+        }
+
+        #[test]
+        fn resolve_tint() {
+            let theme = init("tests/workbook04.xlsx");
+            // accent1 is 2377B6
+            assert_eq!(theme.resolve(4, None), Rgb::Custom(0x23, 0x77, 0xB6));
+            assert_eq!(
+                theme.resolve(4, Some("0.39997558519241921")),
+                Rgb::Custom(0x6C, 0xB0, 0xE3)
+            );
+            assert_eq!(
+                theme.resolve(4, Some("-0.249977111117893")),
+                Rgb::Custom(0x1A, 0x59, 0x89)
+            );
+            // Tints on black and white saturate at the ends
+            assert_eq!(
+                theme.resolve(0, Some("-0.5")),
+                Rgb::Custom(0x80, 0x80, 0x80)
+            );
+            assert_eq!(theme.resolve(1, Some("1")), Rgb::Custom(0xFF, 0xFF, 0xFF));
+            assert_eq!(theme.resolve(12, None), Rgb::Custom(0, 0, 0));
+        }
+
+        #[test]
+        fn read_system_colors_without_last_color() {
+            let theme = init_from_scheme(
+                r#"<a:dk1><a:sysClr val="windowText"/></a:dk1><a:lt1><a:sysClr val="window"/></a:lt1><a:dk2><a:srgbClr val="44546A"/></a:dk2>"#,
+            );
+            assert_eq!(theme.colors.len(), 3);
+            assert_eq!(theme.color(0), Some(Rgb::Custom(0xFF, 0xFF, 0xFF)));
+            assert_eq!(theme.color(1), Some(Rgb::Custom(0, 0, 0)));
+            assert_eq!(theme.color(3), Some(Rgb::Custom(0x44, 0x54, 0x6A)));
+        }
+
+        #[test]
+        fn read_colors_with_modifiers() {
+            let theme = init_from_scheme(
+                r#"<a:dk1><a:sysClr val="windowText" lastClr="000000"/></a:dk1><a:lt1><a:srgbClr val="FFFFFF"><a:lumMod val="95000"/></a:srgbClr></a:lt1><a:dk2><a:srgbClr val="44546A"/></a:dk2>"#,
+            );
+            // The color with children keeps its slot so later indices do not shift
+            assert_eq!(theme.colors.len(), 3);
+            assert_eq!(theme.color(0), Some(Rgb::Custom(0xFF, 0xFF, 0xFF)));
+            assert_eq!(theme.color(3), Some(Rgb::Custom(0x44, 0x54, 0x6A)));
+        }
+    }
+}